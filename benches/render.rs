@@ -0,0 +1,35 @@
+//! How rendering scales with `--jobs` on a long tape.
+//!
+//! Run with `cargo bench --bench render`.
+
+use std::thread;
+
+/// A minute of output at 30fps
+const FRAMES: usize = 1800;
+
+fn main() {
+	let frames = dvd::bench::frames(FRAMES);
+	let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+
+	let mut jobs = vec![1];
+	while jobs.last().unwrap() * 2 <= cpus {
+		jobs.push(jobs.last().unwrap() * 2);
+	}
+	if *jobs.last().unwrap() != cpus {
+		jobs.push(cpus);
+	}
+
+	println!("{} frames", FRAMES);
+	let mut sequential = None;
+	for jobs in jobs {
+		let time = dvd::bench::render(&frames, jobs);
+		let sequential = *sequential.get_or_insert(time);
+		println!(
+			"{:>3} jobs: {:>8.2?} {:>8.1} frames/s {:>5.2}x",
+			jobs,
+			time,
+			FRAMES as f64 / time.as_secs_f64(),
+			sequential.as_secs_f64() / time.as_secs_f64()
+		);
+	}
+}
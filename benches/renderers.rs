@@ -0,0 +1,75 @@
+//! How far the GPU renderer's videos are from the software rasterizer's.
+//!
+//! Both write the same frames, which ffmpeg decodes back at the rasterizer's
+//! size to be compared pixel by pixel, failing when too many are off. Needs
+//! ffmpeg, and is skipped without a GPU.
+//!
+//! Run with `cargo bench --bench renderers`.
+
+use dvd::cli::Renderer;
+use std::path::Path;
+use std::process::Command;
+
+/// Ten seconds of output at 30fps
+const FRAMES: usize = 300;
+
+/// How far a channel can be off before the pixel counts as different,
+/// enough to let through what lossy encoding does to both
+const TOLERANCE: u8 = 24;
+
+/// How many percent of the pixels can differ by more than [`TOLERANCE`].
+/// The GPU renderer draws the log lines' bold text in the regular face and
+/// the cursor as a block, everything else should match.
+const MAX_DIFFERENT: f64 = 2.0;
+
+fn main() {
+	if !dvd::bench::gpu_available() {
+		println!("No GPU to render on, skipping");
+		return;
+	}
+
+	let frames = dvd::bench::frames(FRAMES);
+	let dir = std::env::temp_dir();
+	let (cpu, gpu) = (dir.join("dvd-renderers-cpu.mp4"), dir.join("dvd-renderers-gpu.mp4"));
+	let (width, height) = dvd::bench::video(&frames, Renderer::Cpu, &cpu).unwrap();
+	dvd::bench::video(&frames, Renderer::Gpu, &gpu).unwrap();
+
+	let cpu = decode(&cpu, width, height);
+	let gpu = decode(&gpu, width, height);
+	let size = (width * height * 4) as usize;
+	println!("{}x{}, {} and {} frames", width, height, cpu.len() / size, gpu.len() / size);
+	assert_eq!(cpu.len(), gpu.len(), "The renderers wrote different numbers of frames");
+
+	let (mut error, mut different, mut pixels) = (0u64, 0u64, 0u64);
+	for (a, b) in cpu.chunks_exact(4).zip(gpu.chunks_exact(4)) {
+		let off: Vec<u8> = a[..3].iter().zip(&b[..3]).map(|(a, b)| a.abs_diff(*b)).collect();
+		error += off.iter().map(|&d| d as u64).sum::<u64>();
+		different += off.iter().any(|&d| d > TOLERANCE) as u64;
+		pixels += 1;
+	}
+	let different = different as f64 * 100.0 / pixels as f64;
+	println!(
+		"mean error {:.2} per channel, {:.2}% of pixels off by more than {}",
+		error as f64 / (pixels * 3) as f64,
+		different,
+		TOLERANCE
+	);
+	assert!(
+		different <= MAX_DIFFERENT,
+		"{:.2}% of pixels differ, more than the {}% allowed",
+		different,
+		MAX_DIFFERENT
+	);
+}
+
+/// Every frame of a video as RGBA at `width` by `height`
+fn decode(video: &Path, width: u32, height: u32) -> Vec<u8> {
+	let output = Command::new("ffmpeg")
+		.args(["-loglevel", "error", "-i"])
+		.arg(video)
+		.args(["-vf", &format!("scale={}:{}", width, height), "-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+		.output()
+		.expect("ffmpeg is needed to decode the videos");
+	assert!(output.status.success(), "ffmpeg couldn't decode {}", video.display());
+	output.stdout
+}
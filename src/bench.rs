@@ -0,0 +1,96 @@
+//! Entry points for the benchmarks in `benches/`, which can only reach the
+//! library's public API
+
+use alacritty_terminal::Term;
+use alacritty_terminal::event::VoidListener;
+use alacritty_terminal::term::Config;
+use alacritty_terminal::vte::ansi::Processor;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::burn;
+use crate::cli::Renderer;
+use crate::encode::VideoEncoder;
+use crate::fonts::Fonts;
+use crate::gpu::{self, GpuEncoder};
+use crate::layout::Layout;
+use crate::raster::Rasterizer;
+use crate::record::Frame;
+use crate::render;
+use crate::screen::Screen;
+use crate::settings::Settings;
+use crate::theme::{Palette, to_rgba};
+
+/// A long tape's worth of frames, each a screen of scrolling, colored log
+/// output that differs from the one before
+pub fn frames(count: usize) -> Vec<Frame> {
+	let settings = Settings::default();
+	let fonts = Fonts::bundled();
+	let layout = Layout::new(&settings, &*fonts.regular);
+	let palette = Palette::new(&settings.theme);
+
+	let mut term = Term::new(Config::default(), &layout.term_size(), VoidListener);
+	let mut parser: Processor = Processor::new();
+	(0..count)
+		.map(|i| {
+			let line = format!(
+				"\x1b[3{}m[{:>6}]\x1b[0m \x1b[1mbuild\x1b[0m step {} of {}: compiling module_{}\r\n",
+				i % 7 + 1,
+				i,
+				i,
+				count,
+				i * 31 % 997
+			);
+			parser.advance(&mut term, line.as_bytes());
+			Frame::variable(Screen::capture(&term, &palette), 1)
+		})
+		.collect()
+}
+
+/// How long rendering `frames` takes on `jobs` threads
+pub fn render(frames: &[Frame], jobs: usize) -> Duration {
+	let settings = Settings::default();
+	let fonts = Fonts::bundled();
+	let layout = Layout::new(&settings, &*fonts.regular);
+	let rasterizer = Rasterizer::new(
+		fonts,
+		layout,
+		settings.font_size,
+		to_rgba(settings.theme.background),
+		settings.font_ligatures,
+	);
+
+	let start = Instant::now();
+	render::render_all(&rasterizer, frames.iter().cloned(), jobs, |_| Ok(())).unwrap();
+	start.elapsed()
+}
+
+/// Whether there's anything for `Renderer::Gpu` to draw on
+pub fn gpu_available() -> bool {
+	gpu::available()
+}
+
+/// Write `frames` to a video with `renderer`, returning the size the
+/// software rasterizer draws them at
+pub fn video(frames: &[Frame], renderer: Renderer, output: &Path) -> anyhow::Result<(u32, u32)> {
+	let settings = Settings::default();
+	let fonts = Fonts::bundled();
+	let layout = Layout::new(&settings, &*fonts.regular);
+
+	if renderer == Renderer::Gpu {
+		GpuEncoder::new(output, &layout, &fonts, &settings)?.encode(frames.iter().cloned())?;
+	} else {
+		let encoder = VideoEncoder::new(output, layout.width(), layout.height(), settings.framerate)?;
+		let rasterizer = Rasterizer::new(
+			fonts,
+			layout,
+			settings.font_size,
+			to_rgba(settings.theme.background),
+			settings.font_ligatures,
+		);
+		let jobs = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+		burn::encode(&rasterizer, frames.iter().cloned(), &layout, settings.framerate, jobs, Box::new(encoder))?;
+	}
+	Ok((layout.width(), layout.height()))
+}
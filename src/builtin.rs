@@ -0,0 +1,373 @@
+//! Characters drawn procedurally instead of from the font.
+//!
+//! Box-drawing, block elements, braille and Powerline separators are meant to
+//! tile seamlessly across neighbouring cells. Font glyphs are designed for one
+//! cell size and leave hairline gaps at any other, so like alacritty's builtin
+//! font we draw them at exactly the size of the cell.
+
+/// Coverage for one cell, from 0.0 (untouched) to 1.0 (solid)
+pub struct Canvas {
+	pub width: usize,
+	pub height: usize,
+	pub coverage: Vec<f32>,
+}
+
+/// Samples per axis when anti-aliasing filled shapes
+const SUPERSAMPLE: usize = 4;
+
+impl Canvas {
+	fn new(width: usize, height: usize) -> Self {
+		Self {
+			width,
+			height,
+			coverage: vec![0.0; width * height],
+		}
+	}
+
+	fn add(&mut self, x: usize, y: usize, value: f32) {
+		let pixel = &mut self.coverage[y * self.width + x];
+		*pixel = pixel.max(value.clamp(0.0, 1.0));
+	}
+
+	/// Fill a rectangle, rounding its edges to whole pixels so neighbouring
+	/// cells line up
+	fn rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+		let clamp = |v: f32, max: usize| (v.round().max(0.0) as usize).min(max);
+		let (x0, x1) = (clamp(x0, self.width), clamp(x1, self.width));
+		let (y0, y1) = (clamp(y0, self.height), clamp(y1, self.height));
+		for y in y0..y1 {
+			for x in x0..x1 {
+				self.add(x, y, 1.0);
+			}
+		}
+	}
+
+	/// Fill everywhere `inside` holds, anti-aliased by supersampling
+	fn shape(&mut self, inside: impl Fn(f32, f32) -> bool) {
+		let step = 1.0 / SUPERSAMPLE as f32;
+		for y in 0..self.height {
+			for x in 0..self.width {
+				let mut hits = 0;
+				for sy in 0..SUPERSAMPLE {
+					for sx in 0..SUPERSAMPLE {
+						let px = x as f32 + (sx as f32 + 0.5) * step;
+						let py = y as f32 + (sy as f32 + 0.5) * step;
+						hits += inside(px, py) as usize;
+					}
+				}
+				self.add(x, y, hits as f32 / (SUPERSAMPLE * SUPERSAMPLE) as f32);
+			}
+		}
+	}
+
+	/// Stroke a path given as the distance from a point to it
+	fn stroke(&mut self, thickness: f32, distance: impl Fn(f32, f32) -> f32) {
+		for y in 0..self.height {
+			for x in 0..self.width {
+				let d = distance(x as f32 + 0.5, y as f32 + 0.5);
+				self.add(x, y, thickness / 2.0 + 0.5 - d);
+			}
+		}
+	}
+
+	fn segment(&mut self, thickness: f32, from: (f32, f32), to: (f32, f32)) {
+		self.stroke(thickness, |x, y| segment_distance((x, y), from, to));
+	}
+}
+
+fn segment_distance((x, y): (f32, f32), (x0, y0): (f32, f32), (x1, y1): (f32, f32)) -> f32 {
+	let (dx, dy) = (x1 - x0, y1 - y0);
+	let t = (((x - x0) * dx + (y - y0) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+	((x - x0 - t * dx).powi(2) + (y - y0 - t * dy).powi(2)).sqrt()
+}
+
+/// Whether `c` is drawn here rather than taken from the font
+pub fn is_builtin(c: char) -> bool {
+	matches!(c, '\u{2500}'..='\u{259f}' | '\u{2800}'..='\u{28ff}' | '\u{e0b0}'..='\u{e0bf}')
+}
+
+/// Draw `c` into a cell of the given size, `stroke` being the thickness of a
+/// light line
+pub fn draw(c: char, width: u32, height: u32, stroke: u32) -> Option<Canvas> {
+	let mut canvas = Canvas::new(width as usize, height as usize);
+	let stroke = stroke as f32;
+
+	match c as u32 {
+		0x2500..=0x257f => draw_box(&mut canvas, c, stroke),
+		0x2580..=0x259f => draw_block(&mut canvas, c),
+		0x2800..=0x28ff => draw_braille(&mut canvas, c),
+		0xe0b0..=0xe0bf => draw_powerline(&mut canvas, c, stroke),
+		_ => return None,
+	}
+
+	Some(canvas)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Line {
+	None,
+	Light,
+	Heavy,
+	Double,
+}
+
+/// Which way an arm of a box-drawing character leaves the center
+#[derive(Clone, Copy)]
+enum Arm {
+	Up,
+	Right,
+	Down,
+	Left,
+}
+
+/// The lines leaving the center of U+2500 through U+257F, as up, right, down
+/// and left, with 1 light, 2 heavy and 3 double. Dashes, arcs and diagonals
+/// are left empty and drawn separately.
+const BOX_LINES: [&str; 128] = [
+	// U+2500
+	"0101", "0202", "1010", "2020", "", "", "", "", "", "", "", "", "0110", "0210", "0120", "0220",
+	// U+2510
+	"0011", "0012", "0021", "0022", "1100", "1200", "2100", "2200", "1001", "1002", "2001", "2002", "1110", "1210", "2110", "1120",
+	// U+2520
+	"2120", "2210", "1220", "2220", "1011", "1012", "2011", "1021", "2021", "2012", "1022", "2022", "0111", "0112", "0211", "0212",
+	// U+2530
+	"0121", "0122", "0221", "0222", "1101", "1102", "1201", "1202", "2101", "2102", "2201", "2202", "1111", "1112", "1211", "1212",
+	// U+2540
+	"2111", "1121", "2121", "2112", "2211", "1122", "1221", "2212", "1222", "2122", "2221", "2222", "", "", "", "",
+	// U+2550
+	"0303", "3030", "0310", "0130", "0330", "0013", "0031", "0033", "1300", "3100", "3300", "1003", "3001", "3003", "1310", "3130",
+	// U+2560
+	"3330", "1013", "3031", "3033", "0313", "0131", "0333", "1303", "3101", "3303", "1313", "3131", "3333", "", "", "",
+	// U+2570
+	"", "", "", "", "0001", "1000", "0100", "0010", "0002", "2000", "0200", "0020", "0201", "1020", "0102", "2010",
+];
+
+fn draw_box(canvas: &mut Canvas, c: char, stroke: f32) {
+	let (w, h) = (canvas.width as f32, canvas.height as f32);
+	let (cx, cy) = ((w / 2.0).floor(), (h / 2.0).floor());
+	let heavy = stroke * 2.0;
+
+	match c {
+		// Dashed lines, each size in the order light, heavy, light vertical, heavy vertical
+		'\u{2504}'..='\u{250b}' | '\u{254c}'..='\u{254f}' => {
+			let (dashes, rest) = match c as u32 {
+				n @ 0x2504..=0x2507 => (3, n - 0x2504),
+				n @ 0x2508..=0x250b => (4, n - 0x2508),
+				n => (2, n - 0x254c),
+			};
+			let thickness = if rest % 2 == 1 { heavy } else { stroke };
+			let vertical = rest >= 2;
+			let length = if vertical { h } else { w };
+			let segment = length / dashes as f32;
+			for i in 0..dashes {
+				let start = i as f32 * segment + segment / 4.0;
+				let end = (i + 1) as f32 * segment - segment / 4.0;
+				if vertical {
+					canvas.rect(cx - thickness / 2.0, start, cx + thickness / 2.0, end);
+				} else {
+					canvas.rect(start, cy - thickness / 2.0, end, cy + thickness / 2.0);
+				}
+			}
+		}
+		// Rounded corners, each a quarter circle joined to the edges by straight lines
+		'\u{256d}'..='\u{2570}' => {
+			let radius = cx.min(cy);
+			let (sx, sy) = match c {
+				'╭' => (1.0, 1.0),
+				'╮' => (-1.0, 1.0),
+				'╯' => (-1.0, -1.0),
+				_ => (1.0, -1.0),
+			};
+			let (ox, oy) = (cx + sx * radius, cy + sy * radius);
+			canvas.stroke(stroke, |x, y| {
+				let (dx, dy) = ((x - ox) * -sx, (y - oy) * -sy);
+				if dx >= 0.0 && dy >= 0.0 {
+					((dx * dx + dy * dy).sqrt() - radius).abs()
+				} else {
+					f32::INFINITY
+				}
+			});
+			let toward_x = if sx > 0.0 { w } else { 0.0 };
+			let toward_y = if sy > 0.0 { h } else { 0.0 };
+			canvas.segment(stroke, (ox, cy), (toward_x, cy));
+			canvas.segment(stroke, (cx, oy), (cx, toward_y));
+		}
+		'╱' => canvas.segment(stroke, (w, 0.0), (0.0, h)),
+		'╲' => canvas.segment(stroke, (0.0, 0.0), (w, h)),
+		'╳' => {
+			canvas.segment(stroke, (w, 0.0), (0.0, h));
+			canvas.segment(stroke, (0.0, 0.0), (w, h));
+		}
+		_ => {
+			let lines = BOX_LINES[c as usize - 0x2500].as_bytes();
+			let line = |i: usize| match lines.get(i) {
+				Some(b'1') => Line::Light,
+				Some(b'2') => Line::Heavy,
+				Some(b'3') => Line::Double,
+				_ => Line::None,
+			};
+			let (up, right, down, left) = (line(0), line(1), line(2), line(3));
+
+			let arms = [
+				(Arm::Up, up, left, right),
+				(Arm::Right, right, up, down),
+				(Arm::Down, down, left, right),
+				(Arm::Left, left, up, down),
+			];
+			for (arm, line, before, after) in arms {
+				draw_arm(canvas, (cx, cy), stroke, arm, line, before, after);
+			}
+		}
+	}
+}
+
+/// Draw one arm of a box-drawing character, joining it to the arms either
+/// side of it. `before` is the arm on the negative side across this one, to
+/// the left of a vertical arm or above a horizontal one.
+fn draw_arm(
+	canvas: &mut Canvas,
+	(cx, cy): (f32, f32),
+	stroke: f32,
+	arm: Arm,
+	line: Line,
+	before: Line,
+	after: Line,
+) {
+	let thickness = |line: Line| match line {
+		Line::None => 0.0,
+		Line::Light | Line::Double => stroke,
+		Line::Heavy => stroke * 2.0,
+	};
+	// Double lines are two light lines a light line apart
+	let offset = stroke;
+	// How far past the center a line has to reach to cover one across it
+	let reach = |line: Line| match line {
+		Line::Double => offset + stroke / 2.0,
+		line => thickness(line) / 2.0,
+	};
+
+	// Each stroke as where it starts along the arm, where it sits across it
+	// and how thick it is
+	let mut strokes = Vec::new();
+	match line {
+		Line::None => return,
+		Line::Double => {
+			for (side, across, other) in [(before, -offset, after), (after, offset, before)] {
+				let start = match side {
+					// Stop at the near line so the two doubles leave a gap
+					Line::Double => offset - stroke / 2.0,
+					Line::None => -reach(other),
+					side => -thickness(side) / 2.0,
+				};
+				strokes.push((start, across, stroke));
+			}
+		}
+		line => strokes.push((-(reach(before).max(reach(after))), 0.0, thickness(line))),
+	}
+
+	// Long enough to reach any edge, the canvas clips it
+	let end = (canvas.width + canvas.height) as f32;
+	for (start, across, thickness) in strokes {
+		let (a0, a1) = (start, end);
+		let (b0, b1) = (across - thickness / 2.0, across + thickness / 2.0);
+		match arm {
+			Arm::Up => canvas.rect(cx + b0, cy - a1, cx + b1, cy - a0),
+			Arm::Right => canvas.rect(cx + a0, cy + b0, cx + a1, cy + b1),
+			Arm::Down => canvas.rect(cx + b0, cy + a0, cx + b1, cy + a1),
+			Arm::Left => canvas.rect(cx - a1, cy + b0, cx - a0, cy + b1),
+		}
+	}
+}
+
+fn draw_block(canvas: &mut Canvas, c: char) {
+	let (w, h) = (canvas.width as f32, canvas.height as f32);
+	let eighth = |n: u32| n as f32 / 8.0;
+
+	match c as u32 {
+		0x2580 => canvas.rect(0.0, 0.0, w, h / 2.0),
+		// Lower one eighth through the full block
+		n @ 0x2581..=0x2588 => canvas.rect(0.0, h * (1.0 - eighth(n - 0x2580)), w, h),
+		// Left seven eighths down to one eighth
+		n @ 0x2589..=0x258f => canvas.rect(0.0, 0.0, w * eighth(0x2590 - n), h),
+		0x2590 => canvas.rect(w / 2.0, 0.0, w, h),
+		// Shades are flat coverage, which blends evenly over any background
+		n @ 0x2591..=0x2593 => canvas.coverage.fill((n - 0x2590) as f32 / 4.0),
+		0x2594 => canvas.rect(0.0, 0.0, w, h / 8.0),
+		0x2595 => canvas.rect(w * 7.0 / 8.0, 0.0, w, h),
+		n => {
+			// Quadrants, as upper left, upper right, lower left and lower right bits
+			let quadrants: u8 = match n {
+				0x2596 => 0b0100,
+				0x2597 => 0b1000,
+				0x2598 => 0b0001,
+				0x2599 => 0b1101,
+				0x259a => 0b1001,
+				0x259b => 0b0111,
+				0x259c => 0b1011,
+				0x259d => 0b0010,
+				0x259e => 0b0110,
+				_ => 0b1110,
+			};
+			for i in 0..4 {
+				if quadrants & (1 << i) != 0 {
+					let (x, y) = ((i % 2) as f32, (i / 2) as f32);
+					canvas.rect(x * w / 2.0, y * h / 2.0, (x + 1.0) * w / 2.0, (y + 1.0) * h / 2.0);
+				}
+			}
+		}
+	}
+}
+
+fn draw_braille(canvas: &mut Canvas, c: char) {
+	let (w, h) = (canvas.width as f32, canvas.height as f32);
+	let dots = c as u32 - 0x2800;
+	let radius = (w / 2.0).min(h / 4.0) * 0.3;
+
+	// Dots 1-3 and 4-6 run down each column, 7 and 8 were added underneath
+	let positions = [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (0, 3), (1, 3)];
+	for (bit, (column, row)) in positions.into_iter().enumerate() {
+		if dots & (1 << bit) == 0 {
+			continue;
+		}
+		let x = (column as f32 + 0.5) * w / 2.0;
+		let y = (row as f32 + 0.5) * h / 4.0;
+		canvas.shape(|px, py| (px - x).powi(2) + (py - y).powi(2) <= radius * radius);
+	}
+}
+
+fn draw_powerline(canvas: &mut Canvas, c: char, stroke: f32) {
+	let (w, h) = (canvas.width as f32, canvas.height as f32);
+	let half = h / 2.0;
+
+	match c {
+		// Arrows pointing right and left, solid then outlined
+		'\u{e0b0}' => canvas.shape(|x, y| x <= w * (1.0 - (y - half).abs() / half)),
+		'\u{e0b1}' => {
+			canvas.segment(stroke, (0.0, 0.0), (w, half));
+			canvas.segment(stroke, (w, half), (0.0, h));
+		}
+		'\u{e0b2}' => canvas.shape(|x, y| w - x <= w * (1.0 - (y - half).abs() / half)),
+		'\u{e0b3}' => {
+			canvas.segment(stroke, (w, 0.0), (0.0, half));
+			canvas.segment(stroke, (0.0, half), (w, h));
+		}
+		// Half circles bulging right and left
+		'\u{e0b4}' => canvas.shape(|x, y| (x / w).powi(2) + ((y - half) / half).powi(2) <= 1.0),
+		'\u{e0b5}' => canvas.stroke(stroke, |x, y| {
+			let r = ((x / w).powi(2) + ((y - half) / half).powi(2)).sqrt();
+			(r - 1.0).abs() * w.min(half)
+		}),
+		'\u{e0b6}' => canvas.shape(|x, y| ((w - x) / w).powi(2) + ((y - half) / half).powi(2) <= 1.0),
+		'\u{e0b7}' => canvas.stroke(stroke, |x, y| {
+			let r = (((w - x) / w).powi(2) + ((y - half) / half).powi(2)).sqrt();
+			(r - 1.0).abs() * w.min(half)
+		}),
+		// Triangles filling one corner, and the diagonals they're cut along
+		'\u{e0b8}' => canvas.shape(|x, y| x / w <= y / h),
+		'\u{e0b9}' | '\u{e0bf}' => canvas.segment(stroke, (0.0, 0.0), (w, h)),
+		'\u{e0ba}' => canvas.shape(|x, y| x / w >= 1.0 - y / h),
+		'\u{e0bb}' | '\u{e0bd}' => canvas.segment(stroke, (w, 0.0), (0.0, h)),
+		'\u{e0bc}' => canvas.shape(|x, y| x / w <= 1.0 - y / h),
+		_ => canvas.shape(|x, y| x / w >= y / h),
+	}
+}
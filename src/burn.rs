@@ -1,36 +1,61 @@
 use alacritty_terminal::event::Event;
-use alacritty_terminal::event::{EventListener, WindowSize};
-use alacritty_terminal::event_loop::EventLoop;
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::event_loop::{EventLoop, EventLoopSender, Msg};
 use alacritty_terminal::sync::FairMutex;
 use alacritty_terminal::tty::{self, EventedReadWrite, Options, Shell};
 use alacritty_terminal::{
 	Term,
-	term::{Config, test::TermSize},
+	term::{Config, Osc52, TermMode},
+	vte::ansi::{CursorShape, CursorStyle},
 };
-use dvd_render::image::Rgba;
-use dvd_render::ab_glyph;
-use dvd_render::prelude::*;
-use pollster::FutureExt;
+use dvd_render::image::{RgbaImage, imageops};
 
 // Standard library imports
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::env::current_dir;
 use std::io::Write;
-use std::sync::Arc;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::sync::mpsc::{self, channel};
+use std::thread;
 use std::time::Duration;
-use crate::cli::BurnArgs;
+use crate::cli::{BurnArgs, Outputs, Renderer};
+use crate::csv::CsvWriter;
+use crate::encode::{FrameEncoder, GifEncoder, VideoEncoder};
+use crate::env;
+use crate::fonts::Fonts;
+use crate::gpu::{self, GpuEncoder};
+use crate::keys;
+use crate::layout::Layout;
 use crate::lexer::Lexer;
 use crate::parser::{Parser, Commands};
-
-const WIDTH: usize = 50;
-const HEIGHT: usize = 50;
+use crate::raster::Rasterizer;
+use crate::record::{self, Frame, Recorder, Timeline};
+use crate::render::{self, Rendered};
+use crate::screen::CURSOR_BLINK_INTERVAL;
+use crate::settings::Settings;
+use crate::svg::SvgWriter;
+use crate::theme::{Palette, to_rgba};
+use crate::typing::Typist;
 
 #[derive(Clone)]
 struct Listener {
 	mister: RefCell<Option<mpsc::Sender<()>>>,
 	term: std::sync::OnceLock<Arc<FairMutex<Term<Listener>>>>,
+	/// The tape's clipboard, shared between `Copy`/`Paste` and OSC 52
+	clipboard: Arc<Mutex<String>>,
+	/// Channel back into the pty, for replies the terminal asks us to write
+	pty_tx: Arc<OnceLock<EventLoopSender>>,
+}
+
+impl Listener {
+	fn write_to_pty(&self, text: String) {
+		if let Some(tx) = self.pty_tx.get() {
+			let _ = tx.send(Msg::Input(text.into_bytes().into()));
+		}
+	}
 }
 
 impl EventListener for Listener {
@@ -38,57 +63,221 @@ impl EventListener for Listener {
 		match event {
 			Event::Wakeup => {
 				if let Some(ref sender) = *self.mister.borrow() {
-					println!("AAAA");
-					sender.send(()).unwrap();
-					println!("BBB");
+					// Nobody is listening any more once the recording is done
+					let _ = sender.send(());
 				}
 			}
 			Event::Exit => {
-				println!("{:?}", event);
 				*self.mister.borrow_mut() = None; // This drops the sender
 			}
-			_ => println!("{:?}", event),
+			Event::ClipboardStore(_, text) => {
+				*self.clipboard.lock().unwrap() = text;
+			}
+			Event::ClipboardLoad(_, format) => {
+				let text = format(self.clipboard.lock().unwrap().as_str());
+				self.write_to_pty(text);
+			}
+			Event::PtyWrite(text) => self.write_to_pty(text),
+			// Titles, bells and the like never show up in a recording
+			_ => {}
+		}
+	}
+}
+
+/// Format clipboard text the way a terminal delivers a paste.
+///
+/// In bracketed paste mode the text is wrapped in `ESC [200~`/`ESC [201~` and
+/// stripped of escapes so it can't terminate the paste early, otherwise
+/// newlines are sent as carriage returns like a real terminal would.
+fn paste_bytes(text: &str, bracketed: bool) -> String {
+	if bracketed {
+		format!("\x1b[200~{}\x1b[201~", text.replace('\x1b', ""))
+	} else {
+		text.replace("\r\n", "\r").replace('\n', "\r")
+	}
+}
+
+/// Whether a command does something to the terminal, as opposed to just
+/// configuring the recording
+fn is_action(command: &Commands) -> bool {
+	!matches!(
+		command,
+		Commands::Set(_)
+			| Commands::Env(_)
+			| Commands::EnvFile(_)
+			| Commands::Output(_)
+			| Commands::Require(_)
+			| Commands::Hide
+			| Commands::Show
+	)
+}
+
+/// The name of a command the recorder can't carry out yet
+fn unsupported(command: &Commands) -> Option<String> {
+	match command {
+		Commands::Key(key) if keys::key(&key.key, false).is_none() => Some(format!("{:?}", key.key)),
+		Commands::Ctrl(ctrl) if keys::ctrl(&ctrl.keys).is_none() => Some(format!("Ctrl+{}", ctrl.keys.join("+"))),
+		Commands::Screenshot(_) => Some("Screenshot".to_string()),
+		_ => None,
+	}
+}
+
+/// Write to the shell, returning false once it has gone away
+fn send(pty_writer: &mut impl Write, bytes: &[u8]) -> bool {
+	pty_writer.write_all(bytes).and_then(|_| pty_writer.flush()).is_ok()
+}
+
+/// Press a key `times` times, `rate` apart on the recording's clock,
+/// returning false once the shell has gone away
+fn press<T: EventListener>(
+	pty_writer: &mut impl Write,
+	recorder: &mut Recorder<'_, T>,
+	bytes: &[u8],
+	rate: Duration,
+	times: u32,
+) -> bool {
+	for _ in 0..times {
+		if !send(pty_writer, bytes) {
+			return false;
 		}
+		recorder.capture();
+		recorder.advance(rate);
 	}
+	true
+}
+
+/// Export variables into the already running shell without recording it
+fn export_hidden<T: EventListener>(
+	pty_writer: &mut impl Write,
+	recorder: &mut Recorder<'_, T>,
+	vars: &[(String, String)],
+) {
+	let exports: String = vars
+		.iter()
+		.map(|(variable, value)| env::export_line(variable, value))
+		.collect();
+	// The shell echoes the exports and prints a new prompt, none of which
+	// belongs in the recording
+	recorder.unseen(|| {
+		send(pty_writer, exports.as_bytes());
+	});
 }
 
 pub fn burn(args: &BurnArgs) -> Result<(), ()> {
 	let in_str = std::fs::read_to_string(&args.input_file).unwrap();
 
+	let mut lexer = Lexer::new(&in_str);
+	let mut parser = Parser::new(&mut lexer);
+	let commands = parser.parse();
+
+	let settings = match Settings::from_commands(&commands) {
+		Ok(settings) => settings,
+		Err(e) => {
+			eprintln!("{e:#}");
+			return Err(());
+		}
+	};
+
+	let fonts = match &settings.font_family {
+		Some(family) => match Fonts::load(family) {
+			Ok(fonts) => fonts,
+			Err(e) => {
+				eprintln!("{e:#}");
+				return Err(());
+			}
+		},
+		None => Fonts::bundled(),
+	};
+	let layout = Layout::new(&settings, &*fonts.regular);
+	let output = match open_output(&args.output_file, args.renderer, &layout, &fonts, &settings) {
+		Ok(output) => output,
+		Err(e) => {
+			eprintln!("{e:#}");
+			return Err(());
+		}
+	};
+
+	// Anything set up before the first action goes straight into the shell's
+	// environment, later changes have to be exported from inside it
+	let first_action = commands.iter().position(is_action).unwrap_or(commands.len());
+	let mut spawn_env = HashMap::new();
+	for command in &commands[..first_action] {
+		match command {
+			Commands::Env(env_cmd) => {
+				spawn_env.insert(env_cmd.variable.clone(), env_cmd.value.clone());
+			}
+			Commands::EnvFile(file_cmd) => match env::load_env_file(&file_cmd.path) {
+				Ok(vars) => spawn_env.extend(vars),
+				Err(e) => {
+					eprintln!("{e:#}");
+					return Err(());
+				}
+			},
+			_ => {}
+		}
+	}
+
+	if let Some(name) = commands.iter().find_map(unsupported) {
+		eprintln!("{} isn't supported yet", name);
+		return Err(());
+	}
+
+	let path = env::shell_path(settings.inherit_env, &spawn_env);
+	for command in &commands {
+		if let Commands::Require(require) = command
+			&& !env::find_program(&require.program, &path)
+		{
+			eprintln!("This tape requires {}, which isn't on the shell's PATH", require.program);
+			return Err(());
+		}
+	}
+
 	let (sender, receiver) = channel();
 
 	let sender = RefCell::new(Some(sender));
 	let listener = Listener {
 		mister: sender,
 		term: std::sync::OnceLock::new(),
+		clipboard: Arc::new(Mutex::new(String::new())),
+		pty_tx: Arc::new(OnceLock::new()),
+	};
+	let clipboard = listener.clipboard.clone();
+	let pty_tx = listener.pty_tx.clone();
+
+	let term_config = Config {
+		// Let programs both set and read the tape's clipboard
+		osc52: Osc52::CopyPaste,
+		// Applications can still change this with DECSCUSR
+		default_cursor_style: CursorStyle {
+			shape: CursorShape::Block,
+			blinking: settings.cursor_blink,
+		},
+		..Config::default()
 	};
 
-	let term = Term::new(
-		Config::default(),
-		&TermSize::new(WIDTH, HEIGHT),
+	let mut term = Term::new(
+		term_config,
+		&layout.term_size(),
 		listener.clone(),
 	);
+	// The recorded terminal is the one being typed into
+	term.is_focused = true;
 
-	let shell = Shell::new("/bin/sh".to_string(), vec![]);
+	let (shell, pty_env) = if settings.inherit_env {
+		(Shell::new("/bin/sh".to_string(), vec![]), spawn_env)
+	} else {
+		let args = env::minimal_env_args("/bin/sh", &spawn_env);
+		(Shell::new("env".to_string(), args), HashMap::default())
+	};
 
 	let pty_options = Options {
 		shell: Some(shell),
 		working_directory: Some(current_dir().unwrap()),
 		drain_on_exit: true,
-		env: HashMap::default(),
+		env: pty_env,
 	};
 
-	let mut pty = tty::new(
-		&pty_options,
-		WindowSize {
-			num_lines: 50,
-			num_cols: 50,
-			cell_width: 1,
-			cell_height: 1,
-		},
-		59,
-	)
-	.unwrap();
+	let mut pty = tty::new(&pty_options, layout.window_size(), 59).unwrap();
 
 	let mut pty_writer = pty.writer().try_clone().unwrap(); // Clone the File handle
 
@@ -96,81 +285,247 @@ pub fn burn(args: &BurnArgs) -> Result<(), ()> {
 	let _ = listener.term.set(term.clone());
 
 	let loopp = EventLoop::new(term.clone(), listener, pty, true, false).unwrap();
+	let _ = pty_tx.set(loopp.channel());
 	loopp.spawn();
 
-	// Now you can use pty_writer in your thread
-	std::thread::spawn(move || {
-		let mut lexer = Lexer::new(&in_str);
-		let mut parser = Parser::new(&mut lexer);
-		let mut utf8_buf = [0u8; 4];
-
-		for command in parser.parse().into_iter() {
-			match command {
-				Commands::Type(type_cmd) => {
-					let rate = type_cmd.rate.unwrap_or(Duration::from_millis(50));
-					for c in type_cmd.text.chars() {
-						let len = c.len_utf8();
-						c.encode_utf8(&mut utf8_buf);
-						pty_writer.write_all(&utf8_buf[..len]).unwrap();
-						pty_writer.flush().unwrap();
-						std::thread::sleep(rate);
-					}
-				},
-				_ => todo!()
+	// Whatever the GPU isn't drawing is drawn in software
+	let rasterizer = Rasterizer::new(
+		fonts,
+		layout,
+		settings.font_size,
+		to_rgba(settings.theme.background),
+		settings.font_ligatures,
+	);
+	let jobs = args.jobs.map_or_else(
+		|| thread::available_parallelism().map_or(1, NonZeroUsize::get),
+		NonZeroUsize::get,
+	);
+
+	// Frames are rendered and encoded while the rest of the tape is still
+	// being recorded, so only a handful are ever held at once
+	let (frame_tx, frame_rx) = mpsc::sync_channel(FRAME_BUFFER);
+	let (framerate, loop_offset) = (settings.framerate, settings.loop_offset);
+	let encoder = thread::spawn(move || {
+		let frames = record::rotate(frame_rx.into_iter(), loop_offset)?;
+		match output {
+			Output::Rendered(encoder) => encode(&rasterizer, frames, &layout, framerate, jobs, encoder),
+			Output::Gpu(gpu) => gpu.encode(frames),
+			Output::Svg(mut svg) => {
+				for frame in frames {
+					svg.write_frame(&frame)?;
+				}
+				svg.finish()
+			}
+			Output::Csv(mut csv) => {
+				for frame in frames {
+					csv.write_frame(&frame)?;
+				}
+				csv.finish()
 			}
 		}
 	});
 
-	let mut grid = Grid::<WIDTH, HEIGHT>::default();
+	let timeline = Timeline::new(settings.framerate, settings.playback_speed);
+	let mut recorder = Recorder::new(&term, receiver, Palette::new(&settings.theme), timeline, frame_tx);
+	recorder.start();
 
-	let mut seq = GridSequence::new(Pt(40.0));
-	seq.framerate = core::num::NonZeroU8::new(10).unwrap();
+	let frame = Duration::from_secs(1) / settings.framerate;
+	let mut typist = Typist::new(settings.seed, settings.typing_jitter, settings.typo_rate);
+	let mut utf8_buf = [0u8; 4];
 
-	let mut count = 0;
-	while let Ok(()) = receiver.recv() {
-		let term_term = term.lock();
+	'tape: for (i, command) in commands.into_iter().enumerate() {
+		if recorder.stopped() {
+			// Everything after this would go nowhere
+			break;
+		}
 
-		for cell in term_term.grid().display_iter() {
-			// let fg_color = cell.cell.fg;
-			// let bg_color = cell.cell.bg;
-			let fg_color = Rgba([124, 40, 32, 128]);
-			let bg_color = Rgba([20, 5, 28, 128]);
+		match command {
+			Commands::Type(type_cmd) => {
+				let rate = type_cmd.rate.unwrap_or(settings.typing_speed);
+				for (c, delay) in typist.keystrokes(&type_cmd.text, rate) {
+					if !send(&mut pty_writer, c.encode_utf8(&mut utf8_buf).as_bytes()) {
+						break 'tape;
+					}
+					recorder.capture();
+					recorder.advance(delay);
+				}
+			},
+			Commands::Key(key_cmd) => {
+				let rate = key_cmd.rate.unwrap_or(settings.typing_speed);
+				let app_cursor = term.lock().mode().contains(TermMode::APP_CURSOR);
+				let bytes = keys::key(&key_cmd.key, app_cursor).unwrap_or_default();
+				if !press(&mut pty_writer, &mut recorder, bytes.as_bytes(), rate, key_cmd.repeat_count) {
+					break;
+				}
+			},
+			Commands::Ctrl(ctrl_cmd) => {
+				let rate = ctrl_cmd.rate.unwrap_or(settings.typing_speed);
+				let bytes = keys::ctrl(&ctrl_cmd.keys).unwrap_or_default();
+				if !press(&mut pty_writer, &mut recorder, &bytes, rate, 1) {
+					break;
+				}
+			},
+			Commands::Alt(alt_cmd) => {
+				let rate = alt_cmd.rate.unwrap_or(settings.typing_speed);
+				if !press(&mut pty_writer, &mut recorder, &keys::alt(&alt_cmd.keys[0]), rate, 1) {
+					break;
+				}
+			},
+			Commands::Shift(shift_cmd) => {
+				let rate = shift_cmd.rate.unwrap_or(settings.typing_speed);
+				if !press(&mut pty_writer, &mut recorder, keys::shift(&shift_cmd.keys[0]).as_bytes(), rate, 1) {
+					break;
+				}
+			},
+			Commands::Sleep(sleep_cmd) => {
+				recorder.sleep(sleep_cmd.duration.unwrap_or_default(), frame);
+			},
+			Commands::Wait(wait_cmd) => {
+				let pattern = wait_cmd.pattern.as_ref().unwrap_or(&settings.wait_pattern);
+				let timeout = wait_cmd.timeout.unwrap_or(settings.wait_timeout);
+				if !recorder.wait_for(pattern, &wait_cmd.mode, timeout, frame) {
+					if recorder.stopped() {
+						eprintln!("Recording stopped while waiting for /{}/", pattern);
+						break;
+					}
+					eprintln!("Timed out after {:?} waiting for /{}/", timeout, pattern);
+					return Err(());
+				}
+			},
+			Commands::Copy(copy_cmd) => {
+				*clipboard.lock().unwrap() = copy_cmd.text;
+			},
+			Commands::Paste => {
+				let text = clipboard.lock().unwrap().clone();
+				let bracketed = term.lock().mode().contains(TermMode::BRACKETED_PASTE);
+				if !send(&mut pty_writer, paste_bytes(&text, bracketed).as_bytes()) {
+					break;
+				}
+				recorder.capture();
+			},
+			Commands::Hide => {
+				recorder.capture();
+				recorder.hidden = true;
+			},
+			Commands::Show => {
+				recorder.hidden = false;
+				recorder.capture();
+			},
+			// These were already handed to the shell when it was spawned
+			Commands::Env(_) | Commands::EnvFile(_) if i < first_action => {},
+			Commands::Env(env_cmd) => {
+				export_hidden(&mut pty_writer, &mut recorder, &[(env_cmd.variable, env_cmd.value)]);
+			},
+			Commands::EnvFile(file_cmd) => match env::load_env_file(&file_cmd.path) {
+				Ok(vars) => export_hidden(&mut pty_writer, &mut recorder, &vars),
+				Err(e) => {
+					eprintln!("{e:#}");
+					return Err(());
+				}
+			},
+			// Settings, the output and requirements were all dealt with
+			// before the shell started
+			Commands::Set(_) | Commands::Output(_) | Commands::Require(_) => {},
+			// Turned away before the shell started
+			Commands::Screenshot(_) => {},
+		}
+	}
 
-			println!("{:?}", fg_color);
+	recorder.finish(settings.tail, frame);
+	if let Err(e) = encoder.join().unwrap() {
+		eprintln!("{e:#}");
+		return Err(());
+	}
 
-			grid.set(
-				cell.point.column.0,
-				cell.point.line.0 as usize,
-				GridCell::new_full_color(cell.cell.c, fg_color, bg_color),
-			);
-		}
+	Ok(())
+}
 
-		seq.append(Frame::variable(
-			grid.clone(),
-			core::num::NonZeroU8::new(10).unwrap(),
-		));
+/// How many recorded frames can wait for the renderer before recording
+/// holds off
+const FRAME_BUFFER: usize = 64;
 
-		count += 1;
-		println!("{count}");
+/// Where the recorded frames end up
+enum Output {
+	/// Drawn into images and encoded
+	Rendered(Box<dyn FrameEncoder + Send>),
+	/// Drawn and encoded by dvd-render on the GPU
+	Gpu(Box<GpuEncoder>),
+	/// Written out as text, never drawn at all
+	Svg(Box<SvgWriter>),
+	/// Only the text and timing of each frame
+	Csv(CsvWriter),
+}
 
-		if count == 10 {
-			break;
+/// Set up whatever writes the kind of output the tape asks for
+fn open_output(
+	output: &Path,
+	renderer: Renderer,
+	layout: &Layout,
+	fonts: &Fonts,
+	settings: &Settings,
+) -> anyhow::Result<Output> {
+	let format = Outputs::from_path(output)
+		.ok_or_else(|| anyhow::anyhow!("Don't know how to write {}", output.display()))?;
+	let on_gpu = match renderer {
+		// The GPU renderer leaves out attributes, fallback fonts and cursor
+		// shapes, so it only draws when asked for by name
+		Renderer::Auto | Renderer::Cpu => false,
+		Renderer::Gpu if format != Outputs::Movie => {
+			return Err(anyhow::anyhow!("The GPU renderer can only write videos"));
 		}
-	}
+		Renderer::Gpu if !gpu::available() => {
+			return Err(anyhow::anyhow!("There's no GPU to render on, --renderer cpu works without one"));
+		}
+		Renderer::Gpu => true,
+	};
 
-	seq.append(Frame::variable(
-		grid,
-		core::num::NonZeroU8::new(50).unwrap(),
-	));
+	let (width, height) = (layout.width(), layout.height());
+	Ok(match format {
+		Outputs::Movie if on_gpu => Output::Gpu(Box::new(GpuEncoder::new(output, layout, fonts, settings)?)),
+		Outputs::Movie => Output::Rendered(Box::new(VideoEncoder::new(output, width, height, settings.framerate)?)),
+		Outputs::Gif => Output::Rendered(Box::new(GifEncoder::new(
+			output,
+			width,
+			height,
+			settings.framerate,
+			settings.loop_count,
+		)?)),
+		Outputs::Svg => Output::Svg(Box::new(SvgWriter::new(output, layout, fonts, settings)?)),
+		Outputs::Csv => Output::Csv(CsvWriter::new(output, settings)?),
+	})
+}
 
-	let font = ab_glyph::FontRef::try_from_slice(include_bytes!(
-		"../fonts/liberation_mono/LiberationMono-Regular.ttf"
-	))
-	.unwrap();
-	let renderer = WgpuRenderer::new(font, seq).block_on();
+pub(crate) fn encode(
+	rasterizer: &Rasterizer,
+	frames: impl Iterator<Item = Frame>,
+	layout: &Layout,
+	framerate: u32,
+	jobs: usize,
+	mut encoder: Box<dyn FrameEncoder + Send>,
+) -> anyhow::Result<()> {
+	let blink_frames = ((CURSOR_BLINK_INTERVAL.as_secs_f32() * framerate as f32).round() as u32).max(1);
 
-	let encoder = dvd_render::video::DvdEncoder::new(renderer);
-	encoder.save_video_to(&args.output_file);
+	// Frames only come with the part that changed, the rest is what came before
+	let mut canvas = RgbaImage::new(layout.width(), layout.height());
+	render::render_all(rasterizer, frames, jobs, |rendered| {
+		let Rendered { image, blinked, duration, region } = rendered;
+		imageops::replace(&mut canvas, &image, region.x as i64, region.y as i64);
+		let Some(blinked) = blinked else {
+			return encoder.write_frame(&canvas, region, duration);
+		};
 
-	Ok(())
+		let mut off = canvas.clone();
+		imageops::replace(&mut off, &blinked, region.x as i64, region.y as i64);
+		// Like a real terminal, any change restarts the blink with the cursor on
+		let mut shown = 0;
+		let mut visible = true;
+		while shown < duration {
+			let phase = blink_frames.min(duration - shown);
+			encoder.write_frame(if visible { &canvas } else { &off }, region, phase)?;
+			shown += phase;
+			visible = !visible;
+		}
+		Ok(())
+	})?;
+	encoder.finish()
 }
@@ -1,5 +1,6 @@
-use clap::{Parser, Subcommand, Args};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, Args, ValueEnum};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "vhs")]
@@ -18,7 +19,8 @@ pub struct Cli {
     pub command: Commands
 }
 
-enum Outputs {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outputs {
     Movie,
     Gif,
     Svg,
@@ -26,6 +28,11 @@ enum Outputs {
 }
 
 impl Outputs {
+    /// The kind of output a path asks for, going by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
     fn from_extension(ext: &str) -> Option<Self> {
         match ext.to_lowercase().as_str() {
             "mp4" | "mov" | "avi" | "mkv" | "webm" => Some(Self::Movie),
@@ -116,6 +123,20 @@ pub enum Commands {
     }
 }
 
+/// Which backend draws the frames
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Renderer {
+	/// The software rasterizer, the GPU one leaves out too much to be picked
+	/// without asking
+	#[default]
+	Auto,
+	/// The software rasterizer, which needs no GPU
+	Cpu,
+	/// dvd-render's wgpu renderer, which only writes videos and draws no
+	/// text attributes
+	Gpu,
+}
+
 #[derive(Args)]
 pub struct BurnArgs {
 	/// Input tape file (use "-" for stdin)
@@ -126,5 +147,13 @@ pub struct BurnArgs {
 		value_parser = validate_output_path,
 		value_hint = clap::ValueHint::FilePath
 	)]
-	pub output_file: PathBuf
+	pub output_file: PathBuf,
+
+	/// Backend used to draw the frames
+	#[arg(long, value_enum, default_value_t)]
+	pub renderer: Renderer,
+
+	/// How many frames to render at once, defaults to one per CPU
+	#[arg(short, long)]
+	pub jobs: Option<NonZeroUsize>,
 }
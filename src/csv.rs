@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use dvd_render::image::Rgba;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::record::Frame;
+use crate::screen::Cell;
+use crate::settings::Settings;
+
+/// Writes frames out as a table with one line per row of every frame, for
+/// tools that want what was on screen without reading it back off pixels.
+///
+/// The columns are `frame`, `start` and `duration` in seconds, `row` and
+/// its `text` without trailing blanks. With `Set CsvColors` each row also
+/// gets `fg` and `bg` columns, listing runs of cells as `column+length:#rrggbb`
+/// separated by spaces. `fg` only covers the row's text.
+pub struct CsvWriter {
+	file: BufWriter<File>,
+	framerate: u32,
+	colors: bool,
+	/// Frames written so far
+	index: usize,
+	/// When the next frame starts, in frames at the output's framerate
+	elapsed: u32,
+}
+
+impl CsvWriter {
+	pub fn new(output: &Path, settings: &Settings) -> Result<Self> {
+		let file = File::create(output).with_context(|| format!("Failed to create {}", output.display()))?;
+		let mut file = BufWriter::new(file);
+
+		write!(file, "frame,start,duration,row,text")?;
+		if settings.csv_colors {
+			write!(file, ",fg,bg")?;
+		}
+		writeln!(file)?;
+
+		Ok(Self {
+			file,
+			framerate: settings.framerate,
+			colors: settings.csv_colors,
+			index: 0,
+			elapsed: 0,
+		})
+	}
+
+	pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+		let seconds = |frames: u32| frames as f64 / self.framerate as f64;
+		let start = seconds(self.elapsed);
+		let duration = seconds(frame.duration);
+
+		let screen = &frame.screen;
+		for (row, (text, line)) in screen.text().iter().zip(screen.lines()).enumerate() {
+			write!(self.file, "{},{:.3},{:.3},{},{}", self.index, start, duration, row, quote(text))?;
+			if self.colors {
+				// Past the text the foreground is only the color of blanks
+				let used = line
+					.iter()
+					.rposition(|cell| cell.c != ' ' || !cell.zerowidth.is_empty())
+					.map_or(0, |last| last + 1);
+				let fg = runs(&line[..used], |cell| cell.fg);
+				let bg = runs(line, |cell| cell.bg);
+				write!(self.file, ",{},{}", fg, bg)?;
+			}
+			writeln!(self.file)?;
+		}
+
+		self.index += 1;
+		self.elapsed += frame.duration;
+		Ok(())
+	}
+
+	pub fn finish(mut self) -> Result<()> {
+		self.file.flush()?;
+		Ok(())
+	}
+}
+
+/// Runs of cells sharing a color, as `column+length:#rrggbb`
+fn runs(line: &[Cell], color: impl Fn(&Cell) -> Rgba<u8>) -> String {
+	let mut runs = Vec::new();
+	let mut column = 0;
+	while column < line.len() {
+		let current = color(&line[column]);
+		let length = line[column..].iter().take_while(|cell| color(cell) == current).count();
+		let Rgba([r, g, b, _]) = current;
+		runs.push(format!("{}+{}:#{:02x}{:02x}{:02x}", column, length, r, g, b));
+		column += length;
+	}
+	runs.join(" ")
+}
+
+/// A field as CSV needs it, quoted only when it has to be
+fn quote(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
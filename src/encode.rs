@@ -0,0 +1,292 @@
+use anyhow::{Context, Result, anyhow};
+use dvd_render::image::{Rgba, RgbaImage};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use crate::quantize::Quantizer;
+use crate::render::Region;
+
+/// Something that turns rendered frames into an output file
+pub trait FrameEncoder {
+	/// Show `image` for `duration` frames. Only `region` differs from the
+	/// image before it.
+	fn write_frame(&mut self, image: &RgbaImage, region: Region, duration: u32) -> Result<()>;
+
+	fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Encodes rendered frames into a video by streaming raw pixels to ffmpeg,
+/// which picks the container and codec from the output's extension
+pub struct VideoEncoder {
+	ffmpeg: Child,
+	stdin: ChildStdin,
+}
+
+impl VideoEncoder {
+	pub fn new(output: &Path, width: u32, height: u32, framerate: u32) -> Result<Self> {
+		let mut command = Command::new("ffmpeg");
+		command
+			.args(["-y", "-loglevel", "error"])
+			.args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+			.args(["-s", &format!("{}x{}", width, height)])
+			.args(["-framerate", &framerate.to_string()])
+			.args(["-i", "-"])
+			// Most video codecs need 4:2:0 chroma and therefore even dimensions
+			.args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+			.arg(output)
+			.stdin(Stdio::piped());
+
+		let mut ffmpeg = command.spawn().context("Failed to start ffmpeg, is it installed?")?;
+		let stdin = ffmpeg.stdin.take().unwrap();
+
+		Ok(Self { ffmpeg, stdin })
+	}
+}
+
+impl FrameEncoder for VideoEncoder {
+	fn write_frame(&mut self, image: &RgbaImage, _region: Region, duration: u32) -> Result<()> {
+		for _ in 0..duration {
+			self.stdin
+				.write_all(image.as_raw())
+				.context("ffmpeg stopped accepting frames")?;
+		}
+		Ok(())
+	}
+
+	fn finish(self: Box<Self>) -> Result<()> {
+		drop(self.stdin);
+		let mut ffmpeg = self.ffmpeg;
+		let status = ffmpeg.wait()?;
+		if !status.success() {
+			return Err(anyhow!("ffmpeg exited with {}", status));
+		}
+		Ok(())
+	}
+}
+
+/// Delays shorter than this, in hundredths of a second, get slowed right
+/// down by most viewers
+const MIN_GIF_DELAY: u32 = 2;
+
+/// A frame waiting to be written until it's known how long it stays up
+struct PendingGifFrame {
+	image: RgbaImage,
+	/// Everything that changed since the last frame written
+	region: Region,
+	/// In hundredths of a second
+	start: u32,
+}
+
+/// Encodes frames straight into a GIF.
+///
+/// Each frame only stores the rectangle that changed, with pixels that
+/// stayed the same left transparent, and gets its own palette.
+pub struct GifEncoder {
+	encoder: gif::Encoder<BufWriter<File>>,
+	framerate: u32,
+	/// Frames written so far, at the output's framerate
+	elapsed: u32,
+	/// The last frame written, in the exact colors it was rendered in
+	previous: RgbaImage,
+	pending: Option<PendingGifFrame>,
+}
+
+impl GifEncoder {
+	/// `loop_count` is how many times the GIF plays, 0 for forever
+	pub fn new(output: &Path, width: u32, height: u32, framerate: u32, loop_count: u32) -> Result<Self> {
+		let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height)) else {
+			return Err(anyhow!("{}x{} is too big for a GIF", width, height));
+		};
+
+		let file = File::create(output).with_context(|| format!("Failed to create {}", output.display()))?;
+		let mut encoder = gif::Encoder::new(BufWriter::new(file), gif_width, gif_height, &[])?;
+		match loop_count {
+			0 => encoder.set_repeat(gif::Repeat::Infinite)?,
+			// Without the extension it plays once
+			1 => {}
+			count => encoder.set_repeat(gif::Repeat::Finite((count - 1) as u16))?,
+		}
+
+		Ok(Self {
+			encoder,
+			framerate,
+			elapsed: 0,
+			previous: RgbaImage::new(width, height),
+			pending: None,
+		})
+	}
+
+	/// Hundredths of a second since the start, rounded so delays don't drift
+	fn centiseconds(&self, frames: u32) -> u32 {
+		(frames as f64 * 100.0 / self.framerate as f64).round() as u32
+	}
+
+	fn flush(&mut self, frame: PendingGifFrame, delay: u32) -> Result<()> {
+		let PendingGifFrame { image, region, .. } = frame;
+		let changed = |x: u32, y: u32| image.get_pixel(x, y) != self.previous.get_pixel(x, y);
+
+		// Only store the rectangle around what actually changed
+		let mut bounds: Option<(u32, u32, u32, u32)> = None;
+		for y in region.y..region.y + region.height {
+			for x in region.x..region.x + region.width {
+				if changed(x, y) {
+					bounds = Some(match bounds {
+						Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+						None => (x, y, x, y),
+					});
+				}
+			}
+		}
+		// Nothing changed, but the time still has to pass
+		let (x0, y0, x1, y1) = bounds.unwrap_or((0, 0, 0, 0));
+		let (width, height) = (x1 - x0 + 1, y1 - y0 + 1);
+
+		let mut colors = Vec::new();
+		for y in y0..=y1 {
+			for x in x0..=x1 {
+				if changed(x, y) {
+					let Rgba([r, g, b, _]) = *image.get_pixel(x, y);
+					colors.push([r, g, b]);
+				}
+			}
+		}
+		// One index is kept for leaving pixels as they were
+		let mut quantizer = Quantizer::new(colors, 255);
+		let transparent = quantizer.palette().len() as u8;
+
+		let mut indices = Vec::with_capacity((width * height) as usize);
+		for y in y0..=y1 {
+			for x in x0..=x1 {
+				let pixel = *image.get_pixel(x, y);
+				if pixel == *self.previous.get_pixel(x, y) {
+					indices.push(transparent);
+				} else {
+					let Rgba([r, g, b, _]) = pixel;
+					indices.push(quantizer.index([r, g, b]));
+					self.previous.put_pixel(x, y, pixel);
+				}
+			}
+		}
+
+		let mut palette: Vec<u8> = quantizer.palette().iter().flatten().copied().collect();
+		palette.extend([0, 0, 0]);
+
+		let frame = gif::Frame {
+			delay: delay.min(u16::MAX as u32) as u16,
+			dispose: gif::DisposalMethod::Keep,
+			transparent: Some(transparent),
+			left: x0 as u16,
+			top: y0 as u16,
+			width: width as u16,
+			height: height as u16,
+			palette: Some(palette),
+			buffer: indices.into(),
+			..gif::Frame::default()
+		};
+		self.encoder.write_frame(&frame)?;
+		Ok(())
+	}
+}
+
+impl FrameEncoder for GifEncoder {
+	fn write_frame(&mut self, image: &RgbaImage, region: Region, duration: u32) -> Result<()> {
+		let now = self.centiseconds(self.elapsed);
+		self.elapsed += duration;
+
+		match self.pending.take() {
+			// Too soon after the last one to show, so it gets folded into it
+			Some(mut pending) if now - pending.start < MIN_GIF_DELAY => {
+				pending.image.clone_from(image);
+				pending.region = pending.region.union(region);
+				self.pending = Some(pending);
+				return Ok(());
+			}
+			Some(pending) => {
+				let delay = now - pending.start;
+				self.flush(pending, delay)?;
+			}
+			None => {}
+		}
+
+		self.pending = Some(PendingGifFrame {
+			image: image.clone(),
+			region,
+			start: now,
+		});
+		Ok(())
+	}
+
+	fn finish(mut self: Box<Self>) -> Result<()> {
+		if let Some(pending) = self.pending.take() {
+			let delay = (self.centiseconds(self.elapsed) - pending.start).max(MIN_GIF_DELAY);
+			self.flush(pending, delay)?;
+		}
+		self.encoder.into_inner()?.flush()?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A 2x2 frame in one color, all of it changed
+	fn solid(color: [u8; 3]) -> (RgbaImage, Region) {
+		let [r, g, b] = color;
+		let image = RgbaImage::from_pixel(2, 2, Rgba([r, g, b, 255]));
+		(image, Region { x: 0, y: 0, width: 2, height: 2 })
+	}
+
+	/// Encode a frame per duration, each in a different color, and read back
+	/// how many times the GIF plays and each frame's delay and color
+	fn encode(framerate: u32, loop_count: u32, durations: &[u32]) -> (gif::Repeat, Vec<(u16, [u8; 3])>) {
+		let file = tempfile::NamedTempFile::new().unwrap();
+		let mut encoder: Box<dyn FrameEncoder> =
+			Box::new(GifEncoder::new(file.path(), 2, 2, framerate, loop_count).unwrap());
+		for (i, &duration) in durations.iter().enumerate() {
+			let (image, region) = solid([i as u8 * 10, 0, 0]);
+			encoder.write_frame(&image, region, duration).unwrap();
+		}
+		encoder.finish().unwrap();
+
+		let mut options = gif::DecodeOptions::new();
+		options.set_color_output(gif::ColorOutput::RGBA);
+		let mut decoder = options.read_info(File::open(file.path()).unwrap()).unwrap();
+		let mut frames = Vec::new();
+		while let Some(frame) = decoder.read_next_frame().unwrap() {
+			frames.push((frame.delay, [frame.buffer[0], frame.buffer[1], frame.buffer[2]]));
+		}
+		(decoder.repeat(), frames)
+	}
+
+	fn delays(frames: &[(u16, [u8; 3])]) -> Vec<u16> {
+		frames.iter().map(|&(delay, _)| delay).collect()
+	}
+
+	#[test]
+	fn frames_too_short_to_show_fold_into_the_one_before() {
+		// A hundredth of a second per frame
+		let (_, frames) = encode(100, 0, &[1, 1, 5, 3]);
+		assert_eq!(frames, [(2, [10, 0, 0]), (5, [20, 0, 0]), (3, [30, 0, 0])]);
+	}
+
+	#[test]
+	fn delays_round_without_drifting() {
+		let (_, frames) = encode(30, 0, &[1, 1, 1, 1, 1, 1]);
+		assert_eq!(delays(&frames), [3, 4, 3, 3, 4, 3]);
+	}
+
+	#[test]
+	fn the_last_frame_stays_up_long_enough_to_show() {
+		let (_, frames) = encode(100, 0, &[1]);
+		assert_eq!(delays(&frames), [MIN_GIF_DELAY as u16]);
+	}
+
+	#[test]
+	fn loop_count_is_how_many_times_it_plays() {
+		assert_eq!(encode(10, 0, &[1]).0, gif::Repeat::Infinite);
+		assert_eq!(encode(10, 1, &[1]).0, gif::Repeat::Finite(0));
+		assert_eq!(encode(10, 3, &[1]).0, gif::Repeat::Finite(2));
+	}
+}
@@ -0,0 +1,133 @@
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// `PATH` handed to the shell when a tape sets `InheritEnv false`
+const MINIMAL_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Read a dotenv style file into its variables, in file order.
+///
+/// Supports blank lines, `#` comments, an optional leading `export` and
+/// single or double quoted values. Double quoted values understand the usual
+/// `\n`, `\t`, `\"` and `\\` escapes, single quoted ones are taken verbatim.
+pub fn load_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+	let contents = std::fs::read_to_string(path)
+		.with_context(|| format!("Failed to read env file {}", path.display()))?;
+
+	let mut vars = Vec::new();
+	for (i, line) in contents.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+		let (key, value) = line.split_once('=').ok_or_else(|| {
+			anyhow!("{}:{} expected KEY=VALUE, got '{}'", path.display(), i + 1, line)
+		})?;
+
+		let key = key.trim();
+		if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+			return Err(anyhow!("{}:{} invalid variable name '{}'", path.display(), i + 1, key));
+		}
+
+		let value = parse_value(value.trim())
+			.ok_or_else(|| anyhow!("{}:{} unterminated quote", path.display(), i + 1))?;
+		vars.push((key.to_string(), value));
+	}
+
+	Ok(vars)
+}
+
+fn parse_value(value: &str) -> Option<String> {
+	if let Some(rest) = value.strip_prefix('\'') {
+		let end = rest.find('\'')?;
+		return Some(rest[..end].to_string());
+	}
+
+	if let Some(rest) = value.strip_prefix('"') {
+		let mut out = String::new();
+		let mut chars = rest.chars();
+		while let Some(c) = chars.next() {
+			match c {
+				'"' => return Some(out),
+				'\\' => match chars.next()? {
+					'n' => out.push('\n'),
+					't' => out.push('\t'),
+					other => out.push(other),
+				},
+				c => out.push(c),
+			}
+		}
+		return None;
+	}
+
+	// Unquoted values may carry a trailing comment
+	let value = match value.find(" #") {
+		Some(idx) => &value[..idx],
+		None => value,
+	};
+	Some(value.trim_end().to_string())
+}
+
+/// Quote a value for a POSIX shell, so it survives being typed into the pty.
+fn shell_quote(value: &str) -> String {
+	format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// The line typed into the shell to set a variable once it is already running.
+///
+/// The leading space keeps it out of history for shells that honour
+/// `HISTCONTROL=ignorespace`.
+pub fn export_line(variable: &str, value: &str) -> String {
+	format!(" export {}={}\r", variable, shell_quote(value))
+}
+
+/// Build the arguments for `env -i`, which starts the shell from nothing but
+/// the bare minimum plus the variables the tape asked for.
+pub fn minimal_env_args(shell: &str, vars: &HashMap<String, String>) -> Vec<String> {
+	let mut base = HashMap::from([
+		("PATH".to_string(), MINIMAL_PATH.to_string()),
+		("TERM".to_string(), "xterm-256color".to_string()),
+		("LANG".to_string(), "C.UTF-8".to_string()),
+	]);
+	if let Ok(home) = std::env::var("HOME") {
+		base.insert("HOME".to_string(), home);
+	}
+	if let Ok(user) = std::env::var("USER") {
+		base.insert("USER".to_string(), user);
+	}
+	base.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+	let mut pairs: Vec<_> = base.into_iter().collect();
+	pairs.sort();
+
+	let mut args = vec!["-i".to_string()];
+	args.extend(pairs.into_iter().map(|(k, v)| format!("{}={}", k, v)));
+	args.push(shell.to_string());
+	args
+}
+
+/// The `PATH` the shell ends up with, for finding programs it will run
+pub fn shell_path(inherit: bool, vars: &HashMap<String, String>) -> String {
+	match vars.get("PATH") {
+		Some(path) => path.clone(),
+		None if inherit => std::env::var("PATH").unwrap_or_default(),
+		None => MINIMAL_PATH.to_string(),
+	}
+}
+
+/// Whether `program` is an executable the shell could run, either as a
+/// path or by looking through `path`
+pub fn find_program(program: &str, path: &str) -> bool {
+	let executable = |candidate: &Path| {
+		candidate.metadata().is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+	};
+	if program.contains('/') {
+		return executable(Path::new(program));
+	}
+	path.split(':')
+		.filter(|dir| !dir.is_empty())
+		.any(|dir| executable(&Path::new(dir).join(program)))
+}
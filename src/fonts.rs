@@ -0,0 +1,376 @@
+use anyhow::{Context, Result, anyhow};
+use alacritty_terminal::term::cell::Flags;
+use dvd_render::ab_glyph::{Font, FontArc, FontRef, FontVec};
+use fontdb::{Database, FaceInfo, Family, Query, Weight, ID};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Which face of a font family a cell is drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Style {
+	Regular,
+	Bold,
+	Italic,
+	BoldItalic,
+}
+
+impl Style {
+	pub fn from_flags(flags: Flags) -> Self {
+		match (flags.contains(Flags::BOLD), flags.contains(Flags::ITALIC)) {
+			(false, false) => Style::Regular,
+			(true, false) => Style::Bold,
+			(false, true) => Style::Italic,
+			(true, true) => Style::BoldItalic,
+		}
+	}
+
+	fn is_bold(self) -> bool {
+		matches!(self, Style::Bold | Style::BoldItalic)
+	}
+
+	fn is_italic(self) -> bool {
+		matches!(self, Style::Italic | Style::BoldItalic)
+	}
+}
+
+/// A loaded font, keeping its file around for shaping
+#[derive(Clone)]
+pub struct FontFile {
+	font: FontArc,
+	data: Arc<[u8]>,
+	index: u32,
+}
+
+impl FontFile {
+	fn new(data: Vec<u8>, index: u32) -> Option<Self> {
+		let font = FontVec::try_from_vec_and_index(data.clone(), index).ok()?;
+		Some(Self {
+			font: FontArc::new(font),
+			data: data.into(),
+			index,
+		})
+	}
+
+	/// The font as the shaper reads it
+	pub fn shaping_face(&self) -> Option<rustybuzz::Face<'_>> {
+		rustybuzz::Face::from_slice(&self.data, self.index)
+	}
+
+	/// The font file with the outlines of everything but `glyphs` left out
+	pub fn subset(&self, glyphs: &[u16]) -> Result<Vec<u8>> {
+		subsetter::subset(&self.data, self.index, subsetter::Profile::pdf(glyphs))
+			.map_err(|e| anyhow!("Failed to subset font: {}", e))
+	}
+
+}
+
+impl Deref for FontFile {
+	type Target = FontArc;
+
+	fn deref(&self) -> &FontArc {
+		&self.font
+	}
+}
+
+/// A face to draw with, and whatever it is missing that has to be faked
+pub struct Face<'a> {
+	pub font: &'a FontFile,
+	pub synthetic_bold: bool,
+	pub synthetic_italic: bool,
+	/// Whether this comes from a fallback font, whose metrics don't match the cell
+	pub fallback: bool,
+}
+
+/// The faces of one font family.
+///
+/// Only the regular face is required, missing styles are synthesized from
+/// the closest face we do have.
+#[derive(Clone)]
+pub struct Fonts {
+	/// The family's name, as other programs would ask for it
+	pub family: String,
+	pub regular: FontFile,
+	pub bold: Option<FontFile>,
+	pub italic: Option<FontFile>,
+	pub bold_italic: Option<FontFile>,
+	/// Tried in order for characters the family doesn't have
+	pub fallback: Vec<FontFile>,
+}
+
+impl Fonts {
+	/// Liberation Mono, which ships inside the binary, backed by DejaVu Sans
+	/// and Noto Emoji and then by whatever installed fonts cover the rest
+	pub fn bundled() -> Self {
+		let regular = FontFile::new(
+			include_bytes!("../fonts/liberation_mono/LiberationMono-Regular.ttf").to_vec(),
+			0,
+		)
+		.unwrap();
+
+		let mut db = Database::new();
+		db.load_system_fonts();
+		// Only the regular face ships for now, the others come from an
+		// installed copy of the family and are synthesized without one
+		let styled = |weight, style| load_styled(&db, "Liberation Mono", weight, style);
+
+		Self {
+			family: "Liberation Mono".to_string(),
+			regular,
+			bold: styled(Weight::BOLD, fontdb::Style::Normal),
+			italic: styled(Weight::NORMAL, fontdb::Style::Italic),
+			bold_italic: styled(Weight::BOLD, fontdb::Style::Italic),
+			fallback: fallback(&db),
+		}
+	}
+
+	/// Load a family from the installed fonts, or from a font file when
+	/// given a path to one
+	pub fn load(family: &str) -> Result<Self> {
+		let mut db = Database::new();
+		let path = Path::new(family);
+		let is_file = path.extension().is_some_and(|ext| {
+			matches!(ext.to_string_lossy().to_lowercase().as_str(), "ttf" | "otf" | "ttc" | "otc")
+		});
+
+		let name = if is_file {
+			db.load_font_file(path)
+				.with_context(|| format!("Failed to read font {}", path.display()))?;
+			db.faces()
+				.find_map(|face| face.families.first())
+				.map(|(name, _)| name.clone())
+				.ok_or_else(|| anyhow!("No usable fonts in {}", path.display()))?
+		} else {
+			db.load_system_fonts();
+			find_family(&db, family)?
+		};
+
+		let families = [Family::Name(&name)];
+		let regular = db
+			.query(&Query {
+				families: &families,
+				..Query::default()
+			})
+			.and_then(|id| load_face(&db, id))
+			.ok_or_else(|| anyhow!("Failed to load font family '{}'", name))?;
+		let styled = |weight, style| load_styled(&db, &name, weight, style);
+		let (bold, italic, bold_italic) = (
+			styled(Weight::BOLD, fontdb::Style::Normal),
+			styled(Weight::NORMAL, fontdb::Style::Italic),
+			styled(Weight::BOLD, fontdb::Style::Italic),
+		);
+
+		if is_file {
+			// Left until now so the family could only come from the file
+			db.load_system_fonts();
+		}
+
+		Ok(Self {
+			family: name,
+			regular,
+			bold,
+			italic,
+			bold_italic,
+			fallback: fallback(&db),
+		})
+	}
+
+	/// The face to draw `c` with, going down the fallback chain when the
+	/// family doesn't cover it
+	pub fn face_for(&self, c: char, style: Style) -> Face<'_> {
+		let face = self.face(style);
+		if has_glyph(face.font, c) {
+			return face;
+		}
+
+		let synthesized = |font, fallback| Face {
+			font,
+			synthetic_bold: style.is_bold(),
+			synthetic_italic: style.is_italic(),
+			fallback,
+		};
+
+		// A styled face can be missing glyphs the regular one has
+		if has_glyph(&self.regular, c) {
+			return synthesized(&self.regular, false);
+		}
+		match self.fallback.iter().find(|font| has_glyph(font, c)) {
+			Some(font) => synthesized(font, true),
+			// Nothing has it, let the family draw its missing glyph box
+			None => face,
+		}
+	}
+
+	/// The family's face for `style`, which may have to be synthesized
+	pub fn face(&self, style: Style) -> Face<'_> {
+		let font = match style {
+			Style::Regular => Some(&self.regular),
+			Style::Bold => self.bold.as_ref(),
+			Style::Italic => self.italic.as_ref(),
+			Style::BoldItalic => self.bold_italic.as_ref(),
+		};
+
+		if let Some(font) = font {
+			return Face {
+				font,
+				synthetic_bold: false,
+				synthetic_italic: false,
+				fallback: false,
+			};
+		}
+
+		// Fall back to whichever face gets us closest, faking the rest
+		let (font, has_bold, has_italic) = match (self.bold.as_ref(), self.italic.as_ref()) {
+			(Some(bold), _) if style.is_bold() => (bold, true, false),
+			(_, Some(italic)) if style.is_italic() => (italic, false, true),
+			_ => (&self.regular, false, false),
+		};
+
+		Face {
+			font,
+			synthetic_bold: style.is_bold() && !has_bold,
+			synthetic_italic: style.is_italic() && !has_italic,
+			fallback: false,
+		}
+	}
+}
+
+/// Characters standing in for the scripts and icon sets monospace fonts tend
+/// to leave out, which installed fonts are searched for: Han, kana, Hangul,
+/// and the Powerline and Font Awesome icons Nerd Fonts patch in
+const SYSTEM_FALLBACK_PROBES: [char; 5] = ['中', 'あ', '한', '\u{e0b0}', '\u{f07b}'];
+
+/// DejaVu Sans and Noto Emoji, for the symbols and emoji monospace fonts
+/// tend to leave out, then installed fonts for anything those two don't have
+fn fallback(db: &Database) -> Vec<FontFile> {
+	let mut fallback = vec![
+		FontFile::new(include_bytes!("../fonts/dejavu_sans/DejaVuSans.ttf").to_vec(), 0).unwrap(),
+		FontFile::new(include_bytes!("../fonts/noto_emoji/NotoEmoji-Regular.ttf").to_vec(), 0).unwrap(),
+	];
+
+	let mut missing: Vec<char> = SYSTEM_FALLBACK_PROBES
+		.into_iter()
+		.filter(|&c| !fallback.iter().any(|font| has_glyph(font, c)))
+		.collect();
+
+	// Upright regular monospace faces fit in with the cells best
+	let mut faces: Vec<&FaceInfo> = db.faces().collect();
+	faces.sort_by_key(|face| (!face.monospaced, face.weight != Weight::NORMAL, face.style != fontdb::Style::Normal));
+
+	for face in faces {
+		if missing.is_empty() {
+			break;
+		}
+		// Peek at the font in place, most won't be worth copying
+		let covers = |data: &[u8], index| {
+			let font = FontRef::try_from_slice_and_index(data, index).ok()?;
+			Some(missing.iter().map(|&c| font.glyph_id(c).0 != 0).collect::<Vec<_>>())
+		};
+		let Some(covered) = db.with_face_data(face.id, covers).flatten() else {
+			continue;
+		};
+		if !covered.contains(&true) {
+			continue;
+		}
+		if let Some(font) = load_face(db, face.id) {
+			let mut covered = covered.into_iter();
+			missing.retain(|_| !covered.next().unwrap());
+			fallback.push(font);
+		}
+	}
+
+	fallback
+}
+
+/// The face of `family` for `weight` and `style`, if it really has one.
+///
+/// The closest match can be a different style entirely, which is better
+/// synthesized than drawn with the wrong face.
+fn load_styled(db: &Database, family: &str, weight: Weight, style: fontdb::Style) -> Option<FontFile> {
+	let families = [Family::Name(family)];
+	let id = db.query(&Query {
+		families: &families,
+		weight,
+		style,
+		..Query::default()
+	})?;
+	let face = db.face(id)?;
+	let bold = face.weight >= Weight::SEMIBOLD;
+	let italic = face.style != fontdb::Style::Normal;
+	if bold == (weight >= Weight::SEMIBOLD) && italic == (style != fontdb::Style::Normal) {
+		load_face(db, id)
+	} else {
+		None
+	}
+}
+
+fn load_face(db: &Database, id: ID) -> Option<FontFile> {
+	db.with_face_data(id, |data, index| FontFile::new(data.to_vec(), index))?
+}
+
+/// Lowercase and strip everything but letters and digits, so that
+/// `jetbrains-mono` finds JetBrains Mono
+fn normalize(name: &str) -> String {
+	name.chars()
+		.filter(|c| c.is_alphanumeric())
+		.flat_map(char::to_lowercase)
+		.collect()
+}
+
+/// The installed family `wanted` refers to, or an error suggesting the
+/// closest ones
+fn find_family(db: &Database, wanted: &str) -> Result<String> {
+	let mut families: Vec<&str> = db
+		.faces()
+		.flat_map(|face| face.families.iter().map(|(name, _)| name.as_str()))
+		.collect();
+	families.sort_unstable();
+	families.dedup();
+
+	let normalized = normalize(wanted);
+	if let Some(family) = families.iter().find(|family| normalize(family) == normalized) {
+		return Ok(family.to_string());
+	}
+
+	let mut close: Vec<(usize, &str)> = families
+		.iter()
+		.filter_map(|family| {
+			let candidate = normalize(family);
+			let distance = edit_distance(&normalized, &candidate);
+			let similar = candidate.contains(&normalized)
+				|| normalized.contains(&candidate)
+				|| distance <= (normalized.len() / 3).max(2);
+			similar.then_some((distance, *family))
+		})
+		.collect();
+	close.sort();
+
+	if close.is_empty() {
+		return Err(anyhow!("Font family '{}' is not installed", wanted));
+	}
+	let suggestions: Vec<&str> = close.iter().take(5).map(|(_, family)| *family).collect();
+	Err(anyhow!(
+		"Font family '{}' is not installed, did you mean {}?",
+		wanted,
+		suggestions.join(", ")
+	))
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	for (i, ca) in a.chars().enumerate() {
+		let mut current = vec![i + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = previous[j] + (ca != *cb) as usize;
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+	previous[b.len()]
+}
+
+fn has_glyph(font: &FontArc, c: char) -> bool {
+	font.glyph_id(c).0 != 0
+}
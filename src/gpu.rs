@@ -0,0 +1,172 @@
+use anyhow::{Result, anyhow};
+use dvd_render::ab_glyph::FontArc;
+use dvd_render::prelude::*;
+use dvd_render::video::DvdEncoder;
+use pollster::FutureExt;
+use std::num::NonZeroU8;
+use std::path::{Path, PathBuf};
+use crate::fonts::Fonts;
+use crate::layout::Layout;
+use crate::record;
+use crate::screen::Screen;
+use crate::settings::Settings;
+use crate::theme::to_rgba;
+
+/// How much memory the screens handed to the GPU renderer may take up.
+const GRID_BUDGET: usize = 256 << 20;
+
+/// Calls `$f::<W, H>($args)` with the smallest supported grid that fits
+/// `$columns` x `$rows`, or evaluates `$too_big` if none does.
+///
+/// dvd-render sizes its grids at compile time, so only these sizes can be
+/// rendered. Cells past the terminal's edge are left blank.
+macro_rules! with_grid_size {
+	($columns:expr, $rows:expr, $f:ident $args:tt, $too_big:expr) => {
+		with_grid_size!(@columns $columns, $rows, $f $args, $too_big;
+			[40 60 80 100 120 160 200 250];
+			[12 16 24 32 40 50 64 80])
+	};
+	(@columns $columns:expr, $rows:expr, $f:ident $args:tt, $too_big:expr;
+		[$($w:literal)*]; $heights:tt) => {
+		match $columns {
+			$(c if c <= $w => with_grid_size!(@rows $w, $rows, $f $args, $too_big; $heights),)*
+			_ => $too_big,
+		}
+	};
+	(@rows $w:literal, $rows:expr, $f:ident $args:tt, $too_big:expr; [$($h:literal)*]) => {
+		match $rows {
+			$(r if r <= $h => $f::<$w, $h> $args,)*
+			_ => $too_big,
+		}
+	};
+}
+
+/// Whether wgpu finds an adapter dvd-render can draw on, which has to
+/// support 64-bit integers in shaders.
+///
+/// Headless machines and most CI runners have no GPU, at best they have a
+/// software one like llvmpipe that often can't.
+pub fn available() -> bool {
+	wgpu::Instance::default()
+		.request_adapter(&wgpu::RequestAdapterOptions::default())
+		.block_on()
+		.is_ok_and(|adapter| adapter.features().contains(wgpu::Features::SHADER_INT64))
+}
+
+/// Renders frames with dvd-render's wgpu renderer and encodes them with its
+/// own video encoder, which needs a GPU but no ffmpeg.
+///
+/// Its grid cells only carry a character and two colors, so text attributes,
+/// fallback fonts and cursor shapes are left to the CPU renderer, and the
+/// cursor is always drawn as a block.
+///
+/// The renderer wants the whole recording up front and sizes its cells to
+/// the glyphs in it, so it can't be handed a long one piece by piece without
+/// the pieces coming out at different sizes. Recordings that would take more
+/// than `GRID_BUDGET` to hold are refused rather than filling up memory, the
+/// CPU renderer streams those instead.
+pub struct GpuEncoder {
+	output: PathBuf,
+	layout: Layout,
+	font: FontArc,
+	font_size: u32,
+	framerate: NonZeroU8,
+	/// Drawn wherever the terminal doesn't reach
+	blank: GridCell,
+}
+
+impl GpuEncoder {
+	pub fn new(output: &Path, layout: &Layout, fonts: &Fonts, settings: &Settings) -> Result<Self> {
+		let framerate = u8::try_from(settings.framerate)
+			.ok()
+			.and_then(NonZeroU8::new)
+			.ok_or_else(|| anyhow!("The GPU renderer can't go above {} frames per second", u8::MAX))?;
+
+		Ok(Self {
+			output: output.to_path_buf(),
+			layout: *layout,
+			font: (*fonts.regular).clone(),
+			font_size: settings.font_size,
+			framerate,
+			blank: GridCell::new_full_color(
+				' ',
+				to_rgba(settings.theme.foreground),
+				to_rgba(settings.theme.background),
+			),
+		})
+	}
+
+	/// Render and encode every frame, which all have to be in hand before
+	/// the renderer can start
+	pub fn encode(self, frames: impl Iterator<Item = record::Frame>) -> Result<()> {
+		let Self { output, layout, font, font_size, framerate, blank } = self;
+		with_grid_size!(
+			layout.columns,
+			layout.rows,
+			encode(frames, font, font_size, framerate, blank, &output),
+			Err(anyhow!(
+				"A {}x{} terminal is larger than the biggest grid the GPU renderer can draw",
+				layout.columns,
+				layout.rows
+			))
+		)
+	}
+}
+
+fn encode<const W: usize, const H: usize>(
+	frames: impl Iterator<Item = record::Frame>,
+	font: FontArc,
+	font_size: u32,
+	framerate: NonZeroU8,
+	blank: GridCell,
+	output: &Path,
+) -> Result<()> {
+	let mut seq = GridSequence::<W, H>::new(Pt(font_size as f32));
+	seq.framerate = framerate;
+	let limit = GRID_BUDGET / size_of::<Grid<W, H>>();
+	let mut held = 0;
+
+	for frame in frames {
+		let grid = grid::<W, H>(&frame.screen, blank);
+		// Each of its frames can only stay up for so long
+		let mut remaining = frame.duration;
+		while remaining > 0 {
+			held += 1;
+			if held > limit {
+				return Err(anyhow!(
+					"The recording has more screens than the GPU renderer can hold, --renderer cpu streams them instead"
+				));
+			}
+			let duration = remaining.min(u8::MAX as u32);
+			seq.append(Frame::variable(grid.clone(), NonZeroU8::new(duration as u8).unwrap()));
+			remaining -= duration;
+		}
+	}
+
+	let renderer = WgpuRenderer::new(font, seq).block_on();
+	let encoder = DvdEncoder::new(renderer);
+	encoder.save_video_to(output);
+	Ok(())
+}
+
+fn grid<const W: usize, const H: usize>(screen: &Screen, blank: GridCell) -> Grid<W, H> {
+	let mut grid = Grid::<W, H>::default();
+	for x in 0..W {
+		for y in 0..H {
+			grid.set(x, y, blank);
+		}
+	}
+	for (y, line) in screen.lines().enumerate() {
+		for (x, cell) in line.iter().enumerate() {
+			grid.set(x, y, GridCell::new_full_color(cell.c, cell.fg, cell.bg));
+		}
+	}
+
+	if let Some(cursor) = screen.cursor
+		&& let Some(cell) = screen.cell(cursor.column, cursor.row)
+	{
+		let inverted = GridCell::new_full_color(cell.c, cell.bg, cursor.color);
+		grid.set(cursor.column, cursor.row, inverted);
+	}
+	grid
+}
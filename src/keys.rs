@@ -0,0 +1,124 @@
+use crate::token::TokenType;
+
+/// What a named key sends on its own.
+///
+/// `app_cursor` is whether the program asked for the arrow keys in
+/// application mode, as full screen programs like vim and less do.
+pub fn key(key: &TokenType, app_cursor: bool) -> Option<&'static str> {
+	Some(match key {
+		TokenType::Space => " ",
+		TokenType::Backspace => "\x7f",
+		TokenType::Delete => "\x1b[3~",
+		TokenType::Insert => "\x1b[2~",
+		TokenType::Enter => "\r",
+		TokenType::Escape => "\x1b",
+		TokenType::Tab => "\t",
+		TokenType::PageUp => "\x1b[5~",
+		TokenType::PageDown => "\x1b[6~",
+		TokenType::Up if app_cursor => "\x1bOA",
+		TokenType::Down if app_cursor => "\x1bOB",
+		TokenType::Right if app_cursor => "\x1bOC",
+		TokenType::Left if app_cursor => "\x1bOD",
+		TokenType::Up => "\x1b[A",
+		TokenType::Down => "\x1b[B",
+		TokenType::Right => "\x1b[C",
+		TokenType::Left => "\x1b[D",
+		_ => return None,
+	})
+}
+
+/// What `Ctrl+[Alt+][Shift+]<key>` sends, as the parser spells its keys,
+/// or `None` for a combination terminals have no way to send
+pub fn ctrl(keys: &[String]) -> Option<Vec<u8>> {
+	let (key, modifiers) = keys.split_last()?;
+	let alt = modifiers.iter().any(|m| m == "Alt");
+	let shift = modifiers.iter().any(|m| m == "Shift");
+
+	// Keys with an escape sequence of their own carry the modifiers in it
+	let tilde = match key.as_str() {
+		"Insert" => Some(2),
+		"Delete" => Some(3),
+		_ => None,
+	};
+	if let Some(code) = tilde {
+		let modifier = 1 + 4 + if alt { 2 } else { 0 } + if shift { 1 } else { 0 };
+		return Some(format!("\x1b[{code};{modifier}~").into_bytes());
+	}
+
+	// Everything else is a single control character, which has no room for
+	// Shift, while Alt is an escape in front of it
+	let byte = match key.as_str() {
+		"Enter" => b'\r',
+		"Tab" => b'\t',
+		"Escape" => 0x1b,
+		"Backspace" => 0x08,
+		"Space" | "@" => 0,
+		"-" => 0x1f,
+		"?" => 0x7f,
+		key => match key.as_bytes() {
+			&[c @ (b'a'..=b'z' | b'A'..=b'Z' | b'[' | b'\\' | b']' | b'^' | b'_')] => c.to_ascii_uppercase() & 0x1f,
+			_ => return None,
+		},
+	};
+	Some(if alt { vec![0x1b, byte] } else { vec![byte] })
+}
+
+/// What `Alt+<key>` sends, which is the key with an escape in front
+pub fn alt(key: &str) -> Vec<u8> {
+	let key = match key {
+		"Enter" => "\r",
+		"Tab" => "\t",
+		key => key,
+	};
+	[b"\x1b", key.as_bytes()].concat()
+}
+
+/// What `Shift+<key>` sends on a US layout
+pub fn shift(key: &str) -> String {
+	match key {
+		"Enter" => "\r".to_string(),
+		"Tab" => "\x1b[Z".to_string(),
+		"[" => "{".to_string(),
+		"]" => "}".to_string(),
+		key => key.to_uppercase(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn keys(keys: &[&str]) -> Vec<String> {
+		keys.iter().map(|key| key.to_string()).collect()
+	}
+
+	#[test]
+	fn arrows_follow_the_cursor_mode() {
+		assert_eq!(key(&TokenType::Up, false), Some("\x1b[A"));
+		assert_eq!(key(&TokenType::Up, true), Some("\x1bOA"));
+		assert_eq!(key(&TokenType::Enter, true), Some("\r"));
+		assert_eq!(key(&TokenType::Type, false), None);
+	}
+
+	#[test]
+	fn ctrl_sends_control_characters() {
+		assert_eq!(ctrl(&keys(&["c"])), Some(vec![0x03]));
+		assert_eq!(ctrl(&keys(&["C"])), Some(vec![0x03]));
+		assert_eq!(ctrl(&keys(&["Shift", "c"])), Some(vec![0x03]));
+		assert_eq!(ctrl(&keys(&["Alt", "c"])), Some(vec![0x1b, 0x03]));
+		assert_eq!(ctrl(&keys(&["["])), Some(vec![0x1b]));
+		assert_eq!(ctrl(&keys(&["Space"])), Some(vec![0]));
+		assert_eq!(ctrl(&keys(&["Delete"])), Some(b"\x1b[3;5~".to_vec()));
+		assert_eq!(ctrl(&keys(&["Alt", "Shift", "Insert"])), Some(b"\x1b[2;8~".to_vec()));
+		assert_eq!(ctrl(&keys(&["1"])), None);
+	}
+
+	#[test]
+	fn alt_and_shift() {
+		assert_eq!(alt("x"), b"\x1bx");
+		assert_eq!(alt("Enter"), b"\x1b\r");
+		assert_eq!(shift("Tab"), "\x1b[Z");
+		assert_eq!(shift("a"), "A");
+		assert_eq!(shift("["), "{");
+	}
+}
@@ -0,0 +1,69 @@
+use alacritty_terminal::event::WindowSize;
+use alacritty_terminal::term::test::TermSize;
+use dvd_render::ab_glyph::{Font, PxScale, ScaleFont};
+use crate::settings::Settings;
+
+/// How the terminal is laid out in the output, in cells and in pixels.
+///
+/// This is the single source of truth for the terminal's size, the emulator,
+/// the pty and the renderer are all sized from it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+	pub columns: usize,
+	pub rows: usize,
+	pub cell_width: f32,
+	pub cell_height: f32,
+	pub padding: u32,
+}
+
+impl Layout {
+	pub fn new(settings: &Settings, font: &impl Font) -> Self {
+		let scaled = font.as_scaled(PxScale::from(settings.font_size as f32));
+
+		let cell_width = scaled.h_advance(scaled.glyph_id('0')) + settings.letter_spacing;
+		let cell_height = (scaled.ascent() - scaled.descent() + scaled.line_gap()) * settings.line_height;
+
+		let inner = |outer: u32| outer.saturating_sub(2 * settings.padding) as f32;
+		let columns = match settings.columns {
+			Some(columns) => columns as usize,
+			None => (inner(settings.width) / cell_width) as usize,
+		};
+		let rows = match settings.rows {
+			Some(rows) => rows as usize,
+			None => (inner(settings.height) / cell_height) as usize,
+		};
+
+		// However big the output, the pty can't be told about more than this
+		let limit = u16::MAX as usize;
+		Self {
+			columns: columns.clamp(1, limit),
+			rows: rows.clamp(1, limit),
+			cell_width,
+			cell_height,
+			padding: settings.padding,
+		}
+	}
+
+	/// Output width in pixels, padding included
+	pub fn width(&self) -> u32 {
+		(self.columns as f32 * self.cell_width).round() as u32 + 2 * self.padding
+	}
+
+	/// Output height in pixels, padding included
+	pub fn height(&self) -> u32 {
+		(self.rows as f32 * self.cell_height).round() as u32 + 2 * self.padding
+	}
+
+	pub fn term_size(&self) -> TermSize {
+		TermSize::new(self.columns, self.rows)
+	}
+
+	pub fn window_size(&self) -> WindowSize {
+		WindowSize {
+			num_lines: self.rows as u16,
+			num_cols: self.columns as u16,
+			cell_width: self.cell_width.round() as u16,
+			cell_height: self.cell_height.round() as u16,
+		}
+	}
+}
@@ -1,14 +1,41 @@
 pub mod cli;
+#[doc(hidden)]
+pub mod bench;
 
 mod lexer;
 mod parser;
 mod token;
 
+mod builtin;
 mod burn;
+mod csv;
+mod encode;
+mod env;
+mod fonts;
+mod gpu;
+mod keys;
+mod layout;
+mod quantize;
+mod raster;
+mod record;
+mod render;
+mod screen;
+mod settings;
+mod shape;
+mod spill;
+mod svg;
+mod theme;
+mod theme_import;
+mod themes;
+mod typing;
 
 pub fn run(cli: cli::Cli) -> std::process::ExitCode {
 	let output = match cli.command {
 		cli::Commands::Burn(args) => burn::burn(&args),
+		cli::Commands::Themes { markdown } => {
+			themes::list(markdown);
+			Ok(())
+		}
 		_ => todo!()
 	};
 
@@ -158,9 +158,11 @@ pub enum Setting {
     FontFamily(String),
     Width(u32),
     Height(u32),
+    Columns(u32),
+    Rows(u32),
     LetterSpacing(f32),
     LineHeight(f32),
-    LoopOffset(f32),
+    LoopOffset(LoopOffset),
     Theme(String),
     Padding(u32),
     Framerate(u32),
@@ -174,6 +176,15 @@ pub enum Setting {
     WaitTimeout(Duration),
     WaitPattern(String),
     CursorBlink(bool),
+    InheritEnv(bool),
+    FontLigatures(bool),
+    Tail(Duration),
+    TypingJitter(f32),
+    Seed(u64),
+    TypoRate(f32),
+    LoopCount(u32),
+    EmbedFont(bool),
+    CsvColors(bool),
 }
 
 #[derive(Debug, Clone, Default, PartialEq)]
@@ -213,6 +224,15 @@ impl FromStr for WaitMode {
     }
 }
 
+/// Where a looping output starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopOffset {
+    /// A frame number
+    Frames(u32),
+    /// A percentage of the way through
+    Percent(f32),
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct ScreenshotCommand {
     pub path: PathBuf,
@@ -229,6 +249,11 @@ pub struct EnvCommand {
     pub value: String,
 }
 
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EnvFileCommand {
+    pub path: PathBuf,
+}
+
 #[derive(Debug, Clone)]
 pub enum Commands {
     Type(TypeCommand),
@@ -245,6 +270,7 @@ pub enum Commands {
     Copy(CopyCommand),
     Paste, // No additional data needed
     Env(EnvCommand),
+    EnvFile(EnvFileCommand),
     Hide, // No additional data needed
     Show, // No additional data needed
 }
@@ -315,6 +341,12 @@ impl From<EnvCommand> for Commands {
     }
 }
 
+impl From<EnvFileCommand> for Commands {
+    fn from(cmd: EnvFileCommand) -> Self {
+        Commands::EnvFile(cmd)
+    }
+}
+
 impl From<()> for Commands {
     fn from(_: ()) -> Self {
         Commands::Paste
@@ -396,8 +428,8 @@ impl<'source> Parser<'source> {
             TokenType::Sleep => Ok(self.parse_sleep()?.into()),
             TokenType::Type => Ok(self.parse_type()?.into()),
             TokenType::Ctrl => Ok(self.parse_ctrl()?.into()),
-            TokenType::Alt => Ok(self.parse_alt()?.into()),
-            TokenType::Shift => Ok(self.parse_shift()?.into()),
+            TokenType::Alt => Ok(Commands::Alt(self.parse_alt()?)),
+            TokenType::Shift => Ok(Commands::Shift(self.parse_shift()?)),
             TokenType::Hide => Ok(Commands::Hide),
             TokenType::Require => Ok(self.parse_require()?.into()),
             TokenType::Show => Ok(Commands::Show),
@@ -406,6 +438,7 @@ impl<'source> Parser<'source> {
             TokenType::Copy => Ok(self.parse_copy()?.into()),
             TokenType::Paste => Ok(Commands::Paste),
             TokenType::Env => Ok(self.parse_env()?.into()),
+            TokenType::EnvFile => Ok(self.parse_env_file()?.into()),
             _ => Err(anyhow!("Invalid command: {}", self.current_token.literal)),
         }
     }
@@ -737,6 +770,18 @@ impl<'source> Parser<'source> {
                 Setting::Height(h)
             }
 
+            TokenType::Columns => {
+                let c: u32 = self.peek_token.literal.parse()?;
+                self.next_token();
+                Setting::Columns(c)
+            }
+
+            TokenType::Rows => {
+                let r: u32 = self.peek_token.literal.parse()?;
+                self.next_token();
+                Setting::Rows(r)
+            }
+
             TokenType::LetterSpacing => {
                 let ls: f32 = self.peek_token.literal.parse()?;
                 self.next_token();
@@ -751,16 +796,21 @@ impl<'source> Parser<'source> {
 
             TokenType::LoopOffset => {
                 // might be "25" + "%" token, or "25%"
-                let mut lit = self.peek_token.literal.clone();
+                let lit = self.peek_token.literal.clone();
                 self.next_token();
+                let (lit, mut percent) = match lit.strip_suffix('%') {
+                    Some(number) => (number.to_string(), true),
+                    None => (lit, false),
+                };
                 if self.peek_token.token_type == TokenType::Percent {
                     self.next_token(); // consume '%'
+                    percent = true;
                 }
-                if lit.ends_with('%') {
-                    lit.pop();
+                if percent {
+                    Setting::LoopOffset(LoopOffset::Percent(lit.parse()?))
+                } else {
+                    Setting::LoopOffset(LoopOffset::Frames(lit.parse()?))
                 }
-                let v: f32 = lit.parse()?;
-                Setting::LoopOffset(v)
             }
 
             TokenType::Theme => {
@@ -827,19 +877,26 @@ impl<'source> Parser<'source> {
                 }
                 let val: f64 = self.peek_token.literal.parse()?;
                 self.next_token();
-                let dur = if matches!(
+                let dur = if self.peek_token.token_type == TokenType::WordsPerMinute {
+                    self.next_token();
+                    if val <= 0.0 {
+                        return Err(anyhow!("Set TypingSpeed needs more than 0wpm"));
+                    }
+                    // A word is taken to be five characters
+                    Duration::from_secs_f64(60.0 / (val * 5.0))
+                } else if matches!(
                     self.peek_token.token_type,
                     TokenType::Milliseconds | TokenType::Seconds
                 ) {
                     let unit = self.peek_token.token_type.clone();
                     self.next_token();
                     match unit {
-                        TokenType::Milliseconds => Duration::from_millis(val as u64),
-                        TokenType::Seconds => Duration::from_secs(val as u64),
+                        TokenType::Milliseconds => Duration::from_secs_f64(val / 1000.0),
+                        TokenType::Seconds => Duration::from_secs_f64(val),
                         _ => unreachable!(),
                     }
                 } else {
-                    Duration::from_secs(val as u64)
+                    Duration::from_secs_f64(val)
                 };
                 Setting::TypingSpeed(dur)
             }
@@ -892,6 +949,84 @@ impl<'source> Parser<'source> {
                 Setting::CursorBlink(b)
             }
 
+            TokenType::InheritEnv => {
+                let lit = self.peek_token.literal.clone();
+                let b = match lit.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(anyhow!("Set InheritEnv expects true/false, got {}", lit)),
+                };
+                self.next_token();
+                Setting::InheritEnv(b)
+            }
+
+            TokenType::FontLigatures => {
+                let lit = self.peek_token.literal.clone();
+                let b = match lit.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(anyhow!("Set FontLigatures expects true/false, got {}", lit)),
+                };
+                self.next_token();
+                Setting::FontLigatures(b)
+            }
+
+            TokenType::Tail => {
+                if self.peek_token.token_type != TokenType::Number {
+                    return Err(anyhow!(
+                        "Set Tail expects a duration, got {}",
+                        self.peek_token.literal
+                    ));
+                }
+                Setting::Tail(self.parse_time())
+            }
+
+            TokenType::TypingJitter => {
+                let jitter: f32 = self.peek_token.literal.parse()?;
+                self.next_token();
+                Setting::TypingJitter(jitter)
+            }
+
+            TokenType::Seed => {
+                let seed: u64 = self.peek_token.literal.parse()?;
+                self.next_token();
+                Setting::Seed(seed)
+            }
+
+            TokenType::TypoRate => {
+                let rate: f32 = self.peek_token.literal.parse()?;
+                self.next_token();
+                Setting::TypoRate(rate)
+            }
+
+            TokenType::LoopCount => {
+                let count: u32 = self.peek_token.literal.parse()?;
+                self.next_token();
+                Setting::LoopCount(count)
+            }
+
+            TokenType::EmbedFont => {
+                let lit = self.peek_token.literal.clone();
+                let b = match lit.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(anyhow!("Set EmbedFont expects true/false, got {}", lit)),
+                };
+                self.next_token();
+                Setting::EmbedFont(b)
+            }
+
+            TokenType::CsvColors => {
+                let lit = self.peek_token.literal.clone();
+                let b = match lit.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(anyhow!("Set CsvColors expects true/false, got {}", lit)),
+                };
+                self.next_token();
+                Setting::CsvColors(b)
+            }
+
             // We’ve already guarded with is_setting, so nothing else can happen:
             _ => unreachable!(),
         };
@@ -988,6 +1123,18 @@ impl<'source> Parser<'source> {
         Ok(cmd)
     }
 
+    fn parse_env_file(&mut self) -> Result<EnvFileCommand> {
+        if self.peek_token.token_type != TokenType::String {
+            return Err(anyhow!("{} expects a file path", self.current_token.literal));
+        }
+
+        let cmd = EnvFileCommand {
+            path: PathBuf::from(self.peek_token.literal.clone()),
+        };
+        self.next_token();
+        Ok(cmd)
+    }
+
     fn parse_screenshot(&mut self) -> Result<ScreenshotCommand> {
         let mut cmd = ScreenshotCommand::default();
 
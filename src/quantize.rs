@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+/// Colors closer than this are treated as shades of the same color while
+/// there's room for more distinct ones
+const CLOSE: u32 = 9 * 12 * 12;
+
+/// How different two colors look, roughly weighted by how sensitive the eye
+/// is to each channel
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+	let d = |i: usize| (a[i] as i32 - b[i] as i32).pow(2) as u32;
+	2 * d(0) + 4 * d(1) + 3 * d(2)
+}
+
+/// A palette of at most a few hundred colors, and which to draw any color with.
+///
+/// Terminal frames are mostly a handful of solid colors plus the shades that
+/// antialiasing blends between them. When a frame has few enough colors they
+/// are all kept exactly. Otherwise the most common ones are picked first,
+/// passing over shades of colors already picked, so that text in a rarely
+/// used color isn't crowded out by the blends of the common ones.
+pub struct Quantizer {
+	palette: Vec<[u8; 3]>,
+	nearest: HashMap<[u8; 3], u8>,
+}
+
+impl Quantizer {
+	pub fn new(colors: impl IntoIterator<Item = [u8; 3]>, max: usize) -> Self {
+		let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+		for color in colors {
+			*counts.entry(color).or_default() += 1;
+		}
+
+		let mut by_count: Vec<([u8; 3], u32)> = counts.into_iter().collect();
+		// Ties broken by color so the same frame always gets the same palette
+		by_count.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+		let palette = if by_count.len() <= max {
+			by_count.into_iter().map(|(color, _)| color).collect()
+		} else {
+			let mut palette: Vec<[u8; 3]> = Vec::with_capacity(max);
+			let mut passed = Vec::new();
+			for (color, _) in by_count {
+				if palette.len() == max {
+					break;
+				}
+				if palette.iter().any(|&picked| distance(picked, color) < CLOSE) {
+					passed.push(color);
+				} else {
+					palette.push(color);
+				}
+			}
+			// Any room left goes to the most common shades
+			let room = max - palette.len();
+			palette.extend(passed.into_iter().take(room));
+			palette
+		};
+
+		let nearest = palette
+			.iter()
+			.enumerate()
+			.map(|(i, &color)| (color, i as u8))
+			.collect();
+		Self { palette, nearest }
+	}
+
+	pub fn palette(&self) -> &[[u8; 3]] {
+		&self.palette
+	}
+
+	/// The palette entry that looks most like `color`
+	pub fn index(&mut self, color: [u8; 3]) -> u8 {
+		if let Some(&index) = self.nearest.get(&color) {
+			return index;
+		}
+
+		let index = self
+			.palette
+			.iter()
+			.enumerate()
+			.min_by_key(|&(_, &entry)| distance(entry, color))
+			.map_or(0, |(i, _)| i as u8);
+		self.nearest.insert(color, index);
+		index
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const WHITE: [u8; 3] = [255, 255, 255];
+	const BLACK: [u8; 3] = [0, 0, 0];
+	const RED: [u8; 3] = [200, 0, 0];
+	/// Antialiasing shades of white
+	const LIGHT: [u8; 3] = [250, 250, 250];
+	const LIGHTER: [u8; 3] = [245, 245, 245];
+
+	/// A frame with `count` pixels of each color
+	fn pixels(counts: &[([u8; 3], usize)]) -> Vec<[u8; 3]> {
+		counts.iter().flat_map(|&(color, count)| std::iter::repeat_n(color, count)).collect()
+	}
+
+	fn frame() -> Vec<[u8; 3]> {
+		pixels(&[(WHITE, 100), (BLACK, 90), (LIGHT, 50), (LIGHTER, 40), (RED, 1)])
+	}
+
+	#[test]
+	fn few_colors_are_kept_exactly() {
+		let mut quantizer = Quantizer::new(frame(), 255);
+		assert_eq!(quantizer.palette(), &[WHITE, BLACK, LIGHT, LIGHTER, RED]);
+		for (i, color) in [WHITE, BLACK, LIGHT, LIGHTER, RED].into_iter().enumerate() {
+			assert_eq!(quantizer.index(color), i as u8);
+		}
+	}
+
+	#[test]
+	fn shades_make_way_for_rare_colors() {
+		let mut quantizer = Quantizer::new(frame(), 3);
+		assert_eq!(quantizer.palette(), &[WHITE, BLACK, RED]);
+		assert_eq!(quantizer.index(LIGHT), 0);
+		assert_eq!(quantizer.index(LIGHTER), 0);
+		assert_eq!(quantizer.index([10, 10, 10]), 1);
+		assert_eq!(quantizer.index([180, 20, 10]), 2);
+	}
+
+	#[test]
+	fn room_left_goes_to_the_most_common_shades() {
+		let quantizer = Quantizer::new(frame(), 4);
+		assert_eq!(quantizer.palette(), &[WHITE, BLACK, RED, LIGHT]);
+	}
+
+	#[test]
+	fn ties_always_give_the_same_palette() {
+		let colors = pixels(&[(RED, 5), (BLACK, 5), (WHITE, 5)]);
+		let reversed: Vec<_> = colors.iter().rev().copied().collect();
+		assert_eq!(Quantizer::new(colors, 255).palette(), &[BLACK, RED, WHITE]);
+		assert_eq!(Quantizer::new(reversed, 255).palette(), &[BLACK, RED, WHITE]);
+	}
+}
@@ -0,0 +1,487 @@
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::vte::ansi::CursorShape;
+use dvd_render::ab_glyph::{Font, GlyphId, PxScale, ScaleFont, point};
+use dvd_render::image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use crate::builtin;
+use crate::fonts::{Face, Fonts, Style};
+use crate::layout::Layout;
+use crate::render::Region;
+use crate::screen::{Cell, Cursor, Damage, Screen};
+use crate::shape::Shaper;
+
+/// How far synthetic italics lean, as a fraction of the height above the baseline
+const ITALIC_SHEAR: f32 = 0.2;
+
+/// A glyph's coverage mask, positioned relative to its cell's top left corner
+#[derive(Clone)]
+struct GlyphMask {
+	left: i32,
+	top: i32,
+	width: usize,
+	height: usize,
+	coverage: Vec<f32>,
+}
+
+impl GlyphMask {
+	/// Smear the mask sideways to fake a bold face
+	fn embolden(&mut self, strength: usize) {
+		let width = self.width + strength;
+		let mut coverage = vec![0.0f32; width * self.height];
+		for y in 0..self.height {
+			for x in 0..self.width {
+				let value = self.coverage[y * self.width + x];
+				for dx in 0..=strength {
+					let out = &mut coverage[y * width + x + dx];
+					*out = out.max(value);
+				}
+			}
+		}
+		self.width = width;
+		self.coverage = coverage;
+	}
+
+	/// Lean the mask to fake an italic face, pivoting on the baseline
+	fn shear(&mut self, baseline: f32) {
+		let lean = |y: usize| ((baseline - (self.top + y as i32) as f32) * ITALIC_SHEAR).round() as i32;
+		let max_lean = lean(0).max(0);
+		let min_lean = lean(self.height.saturating_sub(1)).min(0);
+		let width = self.width + (max_lean - min_lean) as usize;
+
+		let mut coverage = vec![0.0f32; width * self.height];
+		for y in 0..self.height {
+			let shift = (lean(y) - min_lean) as usize;
+			let src = &self.coverage[y * self.width..(y + 1) * self.width];
+			coverage[y * width + shift..y * width + shift + self.width].copy_from_slice(src);
+		}
+		self.left += min_lean;
+		self.width = width;
+		self.coverage = coverage;
+	}
+}
+
+/// Draws captured screens into images, entirely on the CPU
+#[derive(Clone)]
+pub struct Rasterizer {
+	fonts: Fonts,
+	layout: Layout,
+	scale: PxScale,
+	/// Distance from the top of a cell to the text's baseline
+	baseline: f32,
+	/// Thickness of underlines and strikethroughs
+	stroke: u32,
+	descent: f32,
+	ascent: f32,
+	background: Rgba<u8>,
+	/// Cached by character, style and how many columns it spans
+	glyphs: HashMap<(char, Style, usize), Option<GlyphMask>>,
+	/// Procedurally drawn characters, cached by the size of the cell they fill
+	builtins: HashMap<(char, u32, u32), Option<GlyphMask>>,
+	/// `None` when ligatures are turned off
+	shaper: Option<Shaper>,
+	/// Glyphs picked by the shaper, cached by glyph id and style
+	shaped: HashMap<(u16, Style), Option<GlyphMask>>,
+}
+
+impl Rasterizer {
+	pub fn new(fonts: Fonts, layout: Layout, font_size: u32, background: Rgba<u8>, ligatures: bool) -> Self {
+		let scale = PxScale::from(font_size as f32);
+		let scaled = fonts.regular.as_scaled(scale);
+		let (ascent, descent) = (scaled.ascent(), scaled.descent());
+
+		// Extra line height is split evenly above and below the text
+		let baseline = (layout.cell_height - (ascent - descent)) / 2.0 + ascent;
+
+		Self {
+			fonts,
+			layout,
+			scale,
+			baseline,
+			stroke: (font_size as f32 / 15.0).round().max(1.0) as u32,
+			descent,
+			ascent,
+			background,
+			glyphs: HashMap::new(),
+			builtins: HashMap::new(),
+			shaper: ligatures.then(Shaper::default),
+			shaped: HashMap::new(),
+		}
+	}
+
+	/// Rasterize `c` for a character spanning `cells` columns
+	fn rasterize(&self, c: char, style: Style, cells: usize) -> Option<GlyphMask> {
+		let face = self.fonts.face_for(c, style);
+		let id = face.font.glyph_id(c);
+		let available = self.layout.cell_width * cells as f32;
+		let advance = face.font.as_scaled(self.scale).h_advance(id);
+
+		// Fallback fonts and wide characters don't share the cell's proportions,
+		// so shrink anything that would spill out of its cells and center it
+		let mut scale = self.scale;
+		let mut x = 0.0;
+		if (face.fallback || cells > 1) && advance > 0.0 {
+			let shrink = (available / advance).min(1.0);
+			scale = PxScale::from(self.scale.y * shrink);
+			x = (available - advance * shrink) / 2.0;
+		}
+
+		let mut mask = self.outline(&face, id, scale, x)?;
+
+		// Combining marks are drawn behind the pen, over whatever came before them
+		if advance == 0.0 && mask.left + mask.width as i32 <= 0 {
+			mask.left += available.round() as i32;
+		}
+
+		Some(mask)
+	}
+
+	/// Rasterize a glyph the shaper picked, at the start of its cell
+	fn rasterize_shaped(&self, id: u16, style: Style) -> Option<GlyphMask> {
+		self.outline(&self.fonts.face(style), GlyphId(id), self.scale, 0.0)
+	}
+
+	fn outline(&self, face: &Face, id: GlyphId, scale: PxScale, x: f32) -> Option<GlyphMask> {
+		let glyph = id.with_scale_and_position(scale, point(x, self.baseline));
+		let outlined = face.font.outline_glyph(glyph)?;
+		let bounds = outlined.px_bounds();
+
+		let width = bounds.width() as usize;
+		let height = bounds.height() as usize;
+		let mut coverage = vec![0.0; width * height];
+		outlined.draw(|x, y, c| {
+			if let Some(value) = coverage.get_mut(y as usize * width + x as usize) {
+				*value = c;
+			}
+		});
+
+		let mut mask = GlyphMask {
+			left: bounds.min.x as i32,
+			top: bounds.min.y as i32,
+			width,
+			height,
+			coverage,
+		};
+		if face.synthetic_bold {
+			mask.embolden(self.stroke as usize);
+		}
+		if face.synthetic_italic {
+			mask.shear(self.baseline);
+		}
+
+		Some(mask)
+	}
+
+	/// Pixel bounds of a cell, rounded so that neighbours tile without gaps
+	fn cell_rect(&self, column: usize, row: usize) -> (u32, u32, u32, u32) {
+		let padding = self.layout.padding as f32;
+		let x = |column: usize| (padding + column as f32 * self.layout.cell_width).round() as u32;
+		let y = |row: usize| (padding + row as f32 * self.layout.cell_height).round() as u32;
+		(x(column), y(row), x(column + 1), y(row + 1))
+	}
+
+	/// The pixels that redrawing the rows in `damage` can touch
+	pub fn damage_region(&self, damage: &Damage) -> Region {
+		let (width, height) = (self.layout.width(), self.layout.height());
+		let Some((first, last)) = damage.bounds() else {
+			return Region { x: 0, y: 0, width, height: 0 };
+		};
+
+		// Glyphs can reach into the rows around theirs, and into the padding
+		let top = match first.checked_sub(1) {
+			Some(row) if row > 0 => self.cell_rect(0, row).1,
+			_ => 0,
+		};
+		let bottom = match last + 1 {
+			row if row + 1 < self.layout.rows => self.cell_rect(0, row).3,
+			_ => height,
+		};
+		Region { x: 0, y: top, width, height: bottom - top }
+	}
+
+	/// Draw just the part of a screen within `region`, leaving out the cursor
+	/// during the off phase of a blink
+	pub fn render(&mut self, screen: &Screen, cursor_visible: bool, region: Region) -> RgbaImage {
+		if region.height == 0 || screen.rows == 0 {
+			return RgbaImage::new(region.width, region.height);
+		}
+
+		// Rows just outside the region can still reach into it
+		let row_at = |y: u32| {
+			((y as f32 - self.layout.padding as f32) / self.layout.cell_height).max(0.0) as usize
+		};
+		let first = row_at(region.y).saturating_sub(1);
+		let last = (row_at(region.y + region.height) + 1).min(screen.rows - 1);
+		let rows = first..last + 1;
+
+		let mut canvas = Canvas {
+			image: RgbaImage::from_pixel(region.width, region.height, self.background),
+			x: region.x,
+			y: region.y,
+		};
+		let lines = || screen.lines().enumerate().skip(rows.start).take(rows.len());
+
+		// Backgrounds go first, wide characters spill over into the next cell
+		for (row, line) in lines() {
+			for (column, cell) in line.iter().enumerate() {
+				fill(&mut canvas, self.cell_rect(column, row), cell.bg);
+			}
+		}
+
+		for (row, line) in lines() {
+			self.draw_line(&mut canvas, row, line);
+			for (column, cell) in line.iter().enumerate() {
+				self.draw_decorations(&mut canvas, self.cell_rect(column, row), cell);
+			}
+		}
+
+		if let Some(cursor) = screen.cursor.filter(|cursor| cursor_visible && rows.contains(&cursor.row)) {
+			self.draw_cursor(&mut canvas, screen, &cursor);
+		}
+
+		canvas.image
+	}
+
+	fn draw_cursor(&mut self, canvas: &mut Canvas, screen: &Screen, cursor: &Cursor) {
+		let Some(cell) = screen.cell(cursor.column, cursor.row) else {
+			return;
+		};
+
+		// Cover both halves of a wide character
+		let (x0, y0, mut x1, y1) = self.cell_rect(cursor.column, cursor.row);
+		if cell.flags.contains(Flags::WIDE_CHAR) {
+			x1 = self.cell_rect(cursor.column + 1, cursor.row).2;
+		}
+		let stroke = self.stroke;
+		// Cells can be thinner than the stroke at tiny font sizes
+		let (right, bottom) = (x1.saturating_sub(stroke).max(x0), y1.saturating_sub(stroke).max(y0));
+
+		match cursor.shape {
+			CursorShape::Block => {
+				// Redraw the character on top in its background color so it stays readable
+				fill(canvas, (x0, y0, x1, y1), cursor.color);
+				let inverted = Cell { fg: cell.bg, ..cell.clone() };
+				self.draw_glyph(canvas, (x0, y0, x1, y1), &inverted);
+			}
+			CursorShape::Beam => fill(canvas, (x0, y0, x0 + stroke, y1), cursor.color),
+			CursorShape::Underline => fill(canvas, (x0, bottom, x1, y1), cursor.color),
+			CursorShape::HollowBlock => {
+				fill(canvas, (x0, y0, x1, y0 + stroke), cursor.color);
+				fill(canvas, (x0, bottom, x1, y1), cursor.color);
+				fill(canvas, (x0, y0, x0 + stroke, y1), cursor.color);
+				fill(canvas, (right, y0, x1, y1), cursor.color);
+			}
+			CursorShape::Hidden => {}
+		}
+	}
+
+	/// Draw a line's text, shaping runs of cells that share a style and color
+	fn draw_line(&mut self, canvas: &mut Canvas, row: usize, line: &[Cell]) {
+		let mut column = 0;
+		while column < line.len() {
+			let cell = &line[column];
+			let mut end = column + 1;
+			if self.shaper.is_some() && is_shapeable(cell) {
+				end += line[end..]
+					.iter()
+					.take_while(|other| is_shapeable(other) && other.fg == cell.fg && other.flags == cell.flags)
+					.count();
+			}
+
+			let run = &line[column..end];
+			if run.len() < 2 || !self.draw_run(canvas, row, column, run) {
+				for (offset, cell) in run.iter().enumerate() {
+					self.draw_glyph(canvas, self.cell_rect(column + offset, row), cell);
+				}
+			}
+			column = end;
+		}
+	}
+
+	/// Draw a run of cells as shaped text, returning false if shaping made no
+	/// difference and they should be drawn one at a time
+	fn draw_run(&mut self, canvas: &mut Canvas, row: usize, first: usize, run: &[Cell]) -> bool {
+		let style = Style::from_flags(run[0].flags);
+		let text: String = run.iter().map(|cell| cell.c).collect();
+		let Some(shaper) = self.shaper.as_mut() else {
+			return false;
+		};
+		let Some(glyphs) = shaper.shape(&self.fonts, style, &text) else {
+			return false;
+		};
+
+		let units = self.fonts.face(style).font.as_scaled(self.scale).h_scale_factor();
+		for glyph in glyphs {
+			let cell = &run[glyph.cell];
+			let rect = self.cell_rect(first + glyph.cell, row);
+			// The family doesn't have this one, go down the fallback chain instead
+			if glyph.id == 0 {
+				self.draw_glyph(canvas, rect, cell);
+				continue;
+			}
+
+			let key = (glyph.id, style);
+			if !self.shaped.contains_key(&key) {
+				let mask = self.rasterize_shaped(glyph.id, style);
+				self.shaped.insert(key, mask);
+			}
+			if let Some(mask) = &self.shaped[&key] {
+				let x = rect.0 as i32 + (glyph.x as f32 * units).round() as i32;
+				let y = rect.1 as i32 - (glyph.y as f32 * units).round() as i32;
+				blit(canvas, (x, y), mask, cell.fg);
+			}
+		}
+
+		true
+	}
+
+	fn draw_glyph(&mut self, canvas: &mut Canvas, (x0, y0, x1, y1): (u32, u32, u32, u32), cell: &Cell) {
+		if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+			return;
+		}
+
+		if builtin::is_builtin(cell.c) {
+			let (width, height) = (x1 - x0, y1 - y0);
+			let stroke = self.stroke;
+			let mask = self.builtins.entry((cell.c, width, height)).or_insert_with(|| {
+				builtin::draw(cell.c, width, height, stroke).map(|canvas| GlyphMask {
+					left: 0,
+					top: 0,
+					width: canvas.width,
+					height: canvas.height,
+					coverage: canvas.coverage,
+				})
+			});
+			if let Some(mask) = mask {
+				blit(canvas, (x0 as i32, y0 as i32), mask, cell.fg);
+			}
+			return;
+		}
+
+		let style = Style::from_flags(cell.flags);
+		let cells = if cell.flags.contains(Flags::WIDE_CHAR) { 2 } else { 1 };
+		if cell.c != ' ' {
+			self.draw_char(canvas, (x0, y0), cell.c, style, cells, cell.fg);
+		}
+		for &mark in &cell.zerowidth {
+			self.draw_char(canvas, (x0, y0), mark, style, cells, cell.fg);
+		}
+	}
+
+	fn draw_char(
+		&mut self,
+		canvas: &mut Canvas,
+		(x0, y0): (u32, u32),
+		c: char,
+		style: Style,
+		cells: usize,
+		color: Rgba<u8>,
+	) {
+		let key = (c, style, cells);
+		if !self.glyphs.contains_key(&key) {
+			let mask = self.rasterize(c, style, cells);
+			self.glyphs.insert(key, mask);
+		}
+		if let Some(mask) = &self.glyphs[&key] {
+			blit(canvas, (x0 as i32, y0 as i32), mask, color);
+		}
+	}
+
+	fn draw_decorations(&self, canvas: &mut Canvas, (x0, y0, x1, y1): (u32, u32, u32, u32), cell: &Cell) {
+		let flags = cell.flags;
+		let stroke = self.stroke;
+		let underline_color = cell.underline.unwrap_or(cell.fg);
+
+		// Keep the underline inside the cell, even with a tight line height
+		let bottom = y1.saturating_sub(stroke).max(y0);
+		let underline_y = (y0 + (self.baseline - self.descent * 0.4).round() as u32).min(bottom);
+
+		if flags.contains(Flags::UNDERLINE) {
+			fill(canvas, (x0, underline_y, x1, underline_y + stroke), underline_color);
+		}
+
+		if flags.contains(Flags::DOUBLE_UNDERLINE) {
+			let lower = (underline_y + 2 * stroke).min(bottom);
+			let upper = lower.saturating_sub(2 * stroke).max(y0);
+			fill(canvas, (x0, upper, x1, upper + stroke), underline_color);
+			fill(canvas, (x0, lower, x1, lower + stroke), underline_color);
+		}
+
+		if flags.contains(Flags::UNDERCURL) {
+			let amplitude = stroke as f32;
+			let period = self.layout.cell_width;
+			for x in x0..x1 {
+				let phase = (x as f32 - self.layout.padding as f32) / period * std::f32::consts::TAU;
+				let y = (underline_y as f32 + amplitude * phase.sin()).round() as u32;
+				let y = y.clamp(y0, bottom);
+				fill(canvas, (x, y, x + 1, y + stroke), underline_color);
+			}
+		}
+
+		if flags.contains(Flags::DOTTED_UNDERLINE) {
+			for x in (x0..x1).filter(|x| (x / stroke).is_multiple_of(2)) {
+				fill(canvas, (x, underline_y, x + 1, underline_y + stroke), underline_color);
+			}
+		}
+
+		if flags.contains(Flags::DASHED_UNDERLINE) {
+			for x in (x0..x1).filter(|x| (x / (stroke * 3)).is_multiple_of(2)) {
+				fill(canvas, (x, underline_y, x + 1, underline_y + stroke), underline_color);
+			}
+		}
+
+		if flags.contains(Flags::STRIKEOUT) {
+			// Roughly halfway up a lowercase letter
+			let y = y0 + (self.baseline - self.ascent * 0.3).round() as u32;
+			fill(canvas, (x0, y, x1, y + stroke), cell.fg);
+		}
+	}
+}
+
+/// The part of the screen being drawn, which is drawn on in screen
+/// coordinates and leaves out whatever falls outside it
+struct Canvas {
+	image: RgbaImage,
+	/// Where the image's top left corner is on the screen
+	x: u32,
+	y: u32,
+}
+
+fn fill(canvas: &mut Canvas, (x0, y0, x1, y1): (u32, u32, u32, u32), color: Rgba<u8>) {
+	let (width, height) = canvas.image.dimensions();
+	for y in y0.max(canvas.y)..y1.min(canvas.y + height) {
+		for x in x0.max(canvas.x)..x1.min(canvas.x + width) {
+			canvas.image.put_pixel(x - canvas.x, y - canvas.y, color);
+		}
+	}
+}
+
+/// Whether a cell can be shaped together with its neighbours
+fn is_shapeable(cell: &Cell) -> bool {
+	cell.zerowidth.is_empty()
+		&& !cell.flags.intersects(Flags::WIDE_CHAR | Flags::WIDE_CHAR_SPACER)
+		&& !builtin::is_builtin(cell.c)
+}
+
+fn blit(canvas: &mut Canvas, (x0, y0): (i32, i32), mask: &GlyphMask, color: Rgba<u8>) {
+	for y in 0..mask.height {
+		for x in 0..mask.width {
+			let px = x0 + mask.left + x as i32;
+			let py = y0 + mask.top + y as i32;
+			blend(canvas, px, py, color, mask.coverage[y * mask.width + x]);
+		}
+	}
+}
+
+fn blend(canvas: &mut Canvas, x: i32, y: i32, color: Rgba<u8>, alpha: f32) {
+	let (x, y) = (x - canvas.x as i32, y - canvas.y as i32);
+	let image = &mut canvas.image;
+	if x < 0 || y < 0 || x as u32 >= image.width() || y as u32 >= image.height() || alpha <= 0.0 {
+		return;
+	}
+
+	let alpha = alpha.min(1.0);
+	let pixel = image.get_pixel_mut(x as u32, y as u32);
+	for i in 0..3 {
+		pixel.0[i] = (pixel.0[i] as f32 * (1.0 - alpha) + color.0[i] as f32 * alpha).round() as u8;
+	}
+}
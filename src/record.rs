@@ -0,0 +1,619 @@
+use anyhow::Result;
+use alacritty_terminal::Term;
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::sync::FairMutex;
+use regex::Regex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::parser::{LoopOffset, WaitMode};
+use crate::screen::{Damage, Screen};
+use crate::spill::Spill;
+use crate::theme::Palette;
+
+/// How long the terminal has to go without redrawing before we consider the
+/// program done reacting to input
+const QUIET: Duration = Duration::from_millis(25);
+
+/// Give up waiting for quiet after this long, for programs that never stop drawing
+const SETTLE_LIMIT: Duration = Duration::from_secs(1);
+
+/// How long the shell gets to draw its first prompt
+const STARTUP_LIMIT: Duration = Duration::from_secs(5);
+
+/// Simulated time since the recording started.
+///
+/// Commands move it forward by exactly as long as they say they take, so the
+/// timeline doesn't depend on how fast the machine or the scheduler is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Clock {
+	now: Duration,
+}
+
+impl Clock {
+	pub fn now(&self) -> Duration {
+		self.now
+	}
+
+	pub fn advance(&mut self, by: Duration) {
+		self.now += by;
+	}
+}
+
+/// The screen as it was at one moment of the recording
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+	pub at: Duration,
+	pub screen: Screen,
+	/// What changed since the snapshot before
+	pub damage: Damage,
+}
+
+/// Takes snapshots of the terminal, stamped with the simulated time, and
+/// passes them on as frames as soon as they're complete
+pub struct Recorder<'a, T> {
+	term: &'a FairMutex<Term<T>>,
+	/// Fires whenever the terminal has something new to draw
+	wakeups: Receiver<()>,
+	palette: Palette,
+	clock: Clock,
+	/// While hidden nothing is recorded and no time passes
+	pub hidden: bool,
+	/// Set once the shell has exited or nothing is taking frames any more,
+	/// after which there's nothing left to record
+	stopped: bool,
+	/// The terminal as of the last capture, kept up to date row by row
+	screen: Option<Screen>,
+	timeline: Timeline,
+	output: SyncSender<Frame>,
+}
+
+impl<'a, T: EventListener> Recorder<'a, T> {
+	pub fn new(
+		term: &'a FairMutex<Term<T>>,
+		wakeups: Receiver<()>,
+		palette: Palette,
+		timeline: Timeline,
+		output: SyncSender<Frame>,
+	) -> Self {
+		Self {
+			term,
+			wakeups,
+			palette,
+			clock: Clock::default(),
+			hidden: false,
+			stopped: false,
+			screen: None,
+			timeline,
+			output,
+		}
+	}
+
+	/// Wait for the shell to start up, and record its first screen
+	pub fn start(&mut self) {
+		if let Err(RecvTimeoutError::Disconnected) = self.wakeups.recv_timeout(STARTUP_LIMIT) {
+			self.stopped = true;
+		}
+		self.capture();
+	}
+
+	pub fn stopped(&self) -> bool {
+		self.stopped
+	}
+
+	/// Let the program finish reacting to whatever it was sent, then record
+	/// the screen at the current simulated time
+	pub fn capture(&mut self) {
+		self.settle();
+		if self.hidden {
+			return;
+		}
+
+		let mut term = self.term.lock();
+		let damage = match &mut self.screen {
+			Some(screen) => screen.update(&mut term, &self.palette),
+			None => {
+				let screen = self.screen.insert(Screen::capture(&term, &self.palette));
+				term.reset_damage();
+				Damage::full(screen.rows)
+			}
+		};
+		drop(term);
+
+		let snapshot = Snapshot {
+			at: self.clock.now(),
+			screen: self.screen.clone().unwrap(),
+			damage,
+		};
+		if let Some(frame) = self.timeline.push(snapshot) {
+			self.emit(frame);
+		}
+	}
+
+	fn emit(&mut self, frame: Frame) {
+		// Only fails when the output has given up, which gets reported there
+		if self.output.send(frame).is_err() {
+			self.stopped = true;
+		}
+	}
+
+	/// Move simulated time forward without waiting for it
+	pub fn advance(&mut self, by: Duration) {
+		if !self.hidden {
+			self.clock.advance(by);
+		}
+	}
+
+	/// Run `send` and let the shell react to it, then put the terminal back
+	/// the way it was so that nothing printed in the meantime is ever seen.
+	///
+	/// The shell is left at a fresh prompt, which looks just like the one
+	/// the terminal is put back to.
+	pub fn unseen(&mut self, send: impl FnOnce()) {
+		let saved = self.term.lock().grid().clone();
+		send();
+		self.settle();
+
+		*self.term.lock().grid_mut() = saved;
+		// The terminal's damage doesn't cover putting the grid back, so the
+		// next capture has to read the whole screen again
+		self.screen = None;
+	}
+
+	/// Let `duration` pass for real so programs can carry on running,
+	/// recording the screen every `step`
+	pub fn sleep(&mut self, duration: Duration, step: Duration) {
+		if self.stopped {
+			// The screen can't change any more, so there's nothing to wait for
+			self.advance(duration);
+			return;
+		}
+
+		let mut slept = Duration::ZERO;
+		while slept < duration {
+			let step = step.min(duration - slept);
+			thread::sleep(step);
+			slept += step;
+			self.advance(step);
+			self.capture();
+		}
+	}
+
+	/// Wait for `pattern` to show up on the last line or anywhere on the
+	/// screen, recording as for [`Recorder::sleep`]. Returns false on timeout.
+	pub fn wait_for(&mut self, pattern: &Regex, mode: &WaitMode, timeout: Duration, step: Duration) -> bool {
+		let mut waited = Duration::ZERO;
+		loop {
+			self.settle();
+			if self.matches(pattern, mode) {
+				return true;
+			}
+			if waited >= timeout || self.stopped {
+				return false;
+			}
+			self.sleep(step, step);
+			waited += step;
+		}
+	}
+
+	fn matches(&self, pattern: &Regex, mode: &WaitMode) -> bool {
+		let screen = Screen::capture(&*self.term.lock(), &self.palette);
+		let lines = screen.text();
+		match mode {
+			WaitMode::Line => lines
+				.iter()
+				.rev()
+				.find(|line| !line.is_empty())
+				.is_some_and(|line| pattern.is_match(line)),
+			WaitMode::Screen => pattern.is_match(&lines.join("\n")),
+		}
+	}
+
+	/// Drain redraws until the terminal goes quiet or the shell exits
+	fn settle(&mut self) {
+		let start = Instant::now();
+		while !self.stopped && start.elapsed() < SETTLE_LIMIT {
+			match self.wakeups.recv_timeout(QUIET) {
+				Ok(()) => {}
+				Err(RecvTimeoutError::Timeout) => break,
+				// The terminal drops its end once the shell is gone
+				Err(RecvTimeoutError::Disconnected) => self.stopped = true,
+			}
+		}
+	}
+
+	/// Record the final screen, and keep it up for `tail`
+	pub fn finish(mut self, tail: Duration, step: Duration) {
+		self.capture();
+		self.sleep(tail, step);
+
+		let end = self.clock.now();
+		for frame in self.timeline.finish(end) {
+			if self.output.send(frame).is_err() {
+				break;
+			}
+		}
+	}
+}
+
+/// A screen of the output and how long it stays up
+#[derive(Debug, Clone)]
+pub struct Frame {
+	pub screen: Screen,
+	/// In frames at the output's framerate
+	pub duration: u32,
+	/// What changed since the frame before
+	pub damage: Damage,
+}
+
+impl Frame {
+	/// A frame that doesn't assume anything about the one before it
+	pub fn variable(screen: Screen, duration: u32) -> Self {
+		let damage = Damage::full(screen.rows);
+		Self { screen, duration, damage }
+	}
+}
+
+/// Turns snapshots into frames as they come in, keeping only what it needs
+/// to finish the next one
+pub struct Timeline {
+	framerate: u32,
+	/// How much faster than it was recorded the output plays
+	speed: f32,
+	/// The newest snapshot, which lasts until the next one
+	current: Option<Snapshot>,
+	/// A finished frame, held back in case the next one shows the same screen
+	pending: Option<Frame>,
+	/// Damage from snapshots too short to make it into a frame, which the
+	/// next frame has to cover
+	skipped: Option<Damage>,
+}
+
+impl Timeline {
+	pub fn new(framerate: u32, speed: f32) -> Self {
+		Self {
+			framerate,
+			speed,
+			current: None,
+			pending: None,
+			skipped: None,
+		}
+	}
+
+	/// The output frame `at` falls in
+	fn frame(&self, at: Duration) -> u32 {
+		// Scaling the timestamps rather than the frames keeps rounding from adding up
+		(at.as_secs_f64() / self.speed as f64 * self.framerate as f64).round() as u32
+	}
+
+	/// Add the screen as of `snapshot.at`, returning a frame once one is complete
+	pub fn push(&mut self, snapshot: Snapshot) -> Option<Frame> {
+		match &mut self.current {
+			// Nothing visible changed, so the current screen just stays up longer
+			Some(current) if current.screen == snapshot.screen => None,
+			// Only the last screen at any moment is ever seen
+			Some(current) if current.at == snapshot.at => {
+				current.screen = snapshot.screen;
+				current.damage.merge(&snapshot.damage);
+				None
+			}
+			_ => {
+				let end = snapshot.at;
+				let previous = self.current.replace(snapshot)?;
+				self.close(previous, end)
+			}
+		}
+	}
+
+	/// Turn `snapshot` into a frame lasting until `end`
+	fn close(&mut self, snapshot: Snapshot, end: Duration) -> Option<Frame> {
+		let duration = self.frame(end).saturating_sub(self.frame(snapshot.at));
+		// Anything replaced within the same frame is never seen
+		if duration == 0 {
+			match &mut self.skipped {
+				Some(skipped) => skipped.merge(&snapshot.damage),
+				None => self.skipped = Some(snapshot.damage),
+			}
+			return None;
+		}
+
+		let skipped = self.skipped.take();
+		// Dropping that can leave the same screen twice in a row
+		if let Some(pending) = &mut self.pending
+			&& pending.screen == snapshot.screen
+		{
+			pending.duration += duration;
+			return None;
+		}
+
+		let mut damage = snapshot.damage;
+		if let Some(skipped) = skipped {
+			damage.merge(&skipped);
+		}
+		self.pending.replace(Frame {
+			screen: snapshot.screen,
+			duration,
+			damage,
+		})
+	}
+
+	/// End the recording at `end`, returning the frames still held back
+	pub fn finish(mut self, end: Duration) -> impl Iterator<Item = Frame> {
+		let last = self.current.take().and_then(|current| self.close(current, end));
+		last.into_iter().chain(self.pending)
+	}
+}
+
+/// Start a looping output `offset` into `frames`, playing the skipped part
+/// at the end instead.
+///
+/// Only the skipped frames are held back, unless the offset is past the end
+/// and that turns out to be all of them. A percentage needs the whole length
+/// first, so until then the frames wait on disk.
+pub fn rotate<'a>(
+	frames: impl Iterator<Item = Frame> + 'a,
+	offset: LoopOffset,
+) -> Result<Box<dyn Iterator<Item = Frame> + 'a>> {
+	let mut frames = frames.fuse();
+	let mut head = Vec::new();
+	let mut start = Vec::new();
+
+	let offset = match offset {
+		LoopOffset::Frames(offset) => offset,
+		LoopOffset::Percent(percent) => return rotate_spilled(frames, percent),
+	};
+
+	let mut skipped = 0;
+	while skipped < offset
+		&& let Some(frame) = frames.next()
+	{
+		skipped += frame.duration;
+		head.push(frame);
+	}
+	if skipped >= offset {
+		// Starting partway through a frame splits it across the loop point
+		if let Some(last) = head.last_mut()
+			&& skipped > offset
+		{
+			let over = skipped - offset;
+			last.duration -= over;
+			start.push(Frame::variable(last.screen.clone(), over));
+		} else if let Some(mut first) = frames.next() {
+			// Nothing comes before it any more
+			first.damage = Damage::full(first.screen.rows);
+			start.push(first);
+		}
+		return Ok(Box::new(start.into_iter().chain(frames).chain(head)));
+	}
+
+	rotate_all(&mut head, offset);
+	Ok(Box::new(head.into_iter()))
+}
+
+/// Rotate by a percentage of the whole recording as for [`rotate`], keeping
+/// the frames on disk until it's known where that falls
+fn rotate_spilled<'a>(
+	frames: impl Iterator<Item = Frame>,
+	percent: f32,
+) -> Result<Box<dyn Iterator<Item = Frame> + 'a>> {
+	let mut spill = Spill::new()?;
+	for frame in frames {
+		spill.push(&frame)?;
+	}
+
+	let durations: Vec<u32> = spill.durations().collect();
+	let total: u32 = durations.iter().sum();
+	let mut offset = match total {
+		0 => 0,
+		total => (total as f32 * percent / 100.0).round() as u32 % total,
+	};
+	let mut split = 0;
+	while offset > 0 && offset >= durations[split] {
+		offset -= durations[split];
+		split += 1;
+	}
+
+	// Starting partway through a frame splits it across the loop point, so it
+	// comes up again at the end
+	let count = durations.len();
+	let order = (split..count).chain(0..split).chain((offset > 0).then_some(split));
+	let frames = spill.replay(order)?.enumerate().map(move |(i, mut frame)| {
+		if i == 0 {
+			frame.duration -= offset;
+			// Nothing comes before it any more
+			frame.damage = Damage::full(frame.screen.rows);
+		} else if i == count {
+			frame.duration = offset;
+		}
+		frame
+	});
+	Ok(Box::new(frames))
+}
+
+/// Rotate a complete list of frames as for [`rotate`]
+fn rotate_all(frames: &mut Vec<Frame>, offset: u32) {
+	let total: u32 = frames.iter().map(|frame| frame.duration).sum();
+	if total == 0 {
+		return;
+	}
+	let mut offset = offset % total;
+	if offset == 0 {
+		return;
+	}
+
+	let mut split = 0;
+	while offset >= frames[split].duration {
+		offset -= frames[split].duration;
+		split += 1;
+	}
+	// Starting partway through a frame splits it across the loop point
+	if offset > 0 {
+		let head = Frame {
+			duration: offset,
+			..frames[split].clone()
+		};
+		frames[split].duration -= offset;
+		frames.insert(split, head);
+		split += 1;
+	}
+	frames.rotate_left(split);
+	frames[0].damage = Damage::full(frames[0].screen.rows);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alacritty_terminal::event::VoidListener;
+	use alacritty_terminal::term::Config;
+	use alacritty_terminal::term::test::TermSize;
+	use alacritty_terminal::vte::ansi::Processor;
+	use crate::theme::Theme;
+
+	const ROWS: usize = 2;
+
+	/// A screen showing `text` on its first line
+	fn screen(text: &str) -> Screen {
+		let mut term = Term::new(Config::default(), &TermSize::new(10, ROWS), VoidListener);
+		let mut parser: Processor = Processor::new();
+		parser.advance(&mut term, text.as_bytes());
+		Screen::capture(&term, &Palette::new(&Theme::default()))
+	}
+
+	fn snapshot(ms: u64, text: &str, damaged: &[usize]) -> Snapshot {
+		let mut damage = Damage::none(ROWS);
+		for &row in damaged {
+			damage.add(row);
+		}
+		Snapshot {
+			at: Duration::from_millis(ms),
+			screen: screen(text),
+			damage,
+		}
+	}
+
+	/// Run snapshots through a timeline ending at `end_ms`
+	fn timeline(framerate: u32, speed: f32, snapshots: Vec<Snapshot>, end_ms: u64) -> Vec<Frame> {
+		let mut timeline = Timeline::new(framerate, speed);
+		let mut frames: Vec<Frame> = snapshots.into_iter().filter_map(|s| timeline.push(s)).collect();
+		frames.extend(timeline.finish(Duration::from_millis(end_ms)));
+		frames
+	}
+
+	/// The first line of each frame and how long it stays up
+	fn summary(frames: &[Frame]) -> Vec<(String, u32)> {
+		frames.iter().map(|frame| (frame.screen.text()[0].clone(), frame.duration)).collect()
+	}
+
+	fn expected(frames: &[(&str, u32)]) -> Vec<(String, u32)> {
+		frames.iter().map(|&(text, duration)| (text.to_string(), duration)).collect()
+	}
+
+	#[test]
+	fn identical_screens_merge_into_one_frame() {
+		let frames = timeline(
+			10,
+			1.0,
+			vec![snapshot(0, "a", &[0]), snapshot(100, "a", &[]), snapshot(300, "b", &[0])],
+			500,
+		);
+		assert_eq!(summary(&frames), expected(&[("a", 3), ("b", 2)]));
+	}
+
+	#[test]
+	fn screens_at_the_same_moment_merge_their_damage() {
+		let frames = timeline(10, 1.0, vec![snapshot(0, "a", &[0]), snapshot(0, "b", &[1])], 200);
+		assert_eq!(summary(&frames), expected(&[("b", 2)]));
+		assert_eq!(frames[0].damage.rows().collect::<Vec<_>>(), [0, 1]);
+	}
+
+	#[test]
+	fn skipped_screens_pass_their_damage_on() {
+		// b is replaced before its frame comes around, so c has to redraw its row
+		let frames = timeline(
+			10,
+			1.0,
+			vec![snapshot(0, "a", &[0]), snapshot(120, "b", &[1]), snapshot(140, "c", &[0])],
+			300,
+		);
+		assert_eq!(summary(&frames), expected(&[("a", 1), ("c", 2)]));
+		assert_eq!(frames[1].damage.rows().collect::<Vec<_>>(), [0, 1]);
+	}
+
+	#[test]
+	fn a_screen_back_after_a_skipped_one_stays_one_frame() {
+		let frames = timeline(
+			10,
+			1.0,
+			vec![snapshot(0, "a", &[0]), snapshot(120, "b", &[0]), snapshot(140, "a", &[0])],
+			300,
+		);
+		assert_eq!(summary(&frames), expected(&[("a", 3)]));
+	}
+
+	#[test]
+	fn playback_speed_scales_durations() {
+		let snapshots = || vec![snapshot(0, "a", &[0]), snapshot(1000, "b", &[0])];
+		assert_eq!(summary(&timeline(10, 2.0, snapshots(), 2000)), expected(&[("a", 5), ("b", 5)]));
+		assert_eq!(summary(&timeline(10, 0.5, snapshots(), 2000)), expected(&[("a", 20), ("b", 20)]));
+		// Rounding the timestamps keeps the total right even when no frame is
+		assert_eq!(summary(&timeline(10, 3.0, snapshots(), 2000)), expected(&[("a", 3), ("b", 4)]));
+	}
+
+	/// Three frames, ten long in all, none of them damaged
+	fn frames() -> Vec<Frame> {
+		[("a", 3), ("b", 2), ("c", 5)]
+			.into_iter()
+			.map(|(text, duration)| Frame {
+				screen: screen(text),
+				duration,
+				damage: Damage::none(ROWS),
+			})
+			.collect()
+	}
+
+	fn rotated(offset: LoopOffset) -> Vec<Frame> {
+		rotate(frames().into_iter(), offset).unwrap().collect()
+	}
+
+	#[test]
+	fn rotate_starts_the_loop_at_the_offset() {
+		let cases = [
+			(LoopOffset::Frames(0), expected(&[("a", 3), ("b", 2), ("c", 5)])),
+			(LoopOffset::Frames(3), expected(&[("b", 2), ("c", 5), ("a", 3)])),
+			// Partway through b, so it shows up on both ends
+			(LoopOffset::Frames(4), expected(&[("b", 1), ("c", 5), ("a", 3), ("b", 1)])),
+			// Past the end wraps around
+			(LoopOffset::Frames(14), expected(&[("b", 1), ("c", 5), ("a", 3), ("b", 1)])),
+			(LoopOffset::Frames(10), expected(&[("a", 3), ("b", 2), ("c", 5)])),
+			(LoopOffset::Percent(0.0), expected(&[("a", 3), ("b", 2), ("c", 5)])),
+			(LoopOffset::Percent(30.0), expected(&[("b", 2), ("c", 5), ("a", 3)])),
+			(LoopOffset::Percent(40.0), expected(&[("b", 1), ("c", 5), ("a", 3), ("b", 1)])),
+			(LoopOffset::Percent(100.0), expected(&[("a", 3), ("b", 2), ("c", 5)])),
+		];
+		for (offset, expected) in cases {
+			let frames = rotated(offset);
+			assert_eq!(summary(&frames), expected, "{offset:?}");
+		}
+	}
+
+	#[test]
+	fn rotated_frames_start_fully_damaged() {
+		let offsets = [
+			LoopOffset::Frames(3),
+			LoopOffset::Frames(4),
+			LoopOffset::Frames(14),
+			LoopOffset::Percent(40.0),
+		];
+		for offset in offsets {
+			let frames = rotated(offset);
+			assert_eq!(frames[0].damage, Damage::full(ROWS), "{offset:?}");
+		}
+	}
+
+	#[test]
+	fn rotate_keeps_an_empty_recording_empty() {
+		for offset in [LoopOffset::Frames(4), LoopOffset::Percent(40.0)] {
+			assert!(rotate(std::iter::empty(), offset).unwrap().next().is_none());
+		}
+	}
+}
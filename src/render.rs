@@ -0,0 +1,136 @@
+use anyhow::Result;
+use dvd_render::image::RgbaImage;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use crate::raster::Rasterizer;
+use crate::record::Frame;
+
+/// A rectangle of a rendered frame, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Region {
+	pub fn is_empty(&self) -> bool {
+		self.width == 0 || self.height == 0
+	}
+
+	/// The smallest region covering both
+	pub fn union(self, other: Region) -> Region {
+		if self.is_empty() {
+			return other;
+		}
+		if other.is_empty() {
+			return self;
+		}
+		let x = self.x.min(other.x);
+		let y = self.y.min(other.y);
+		Region {
+			x,
+			y,
+			width: (self.x + self.width).max(other.x + other.width) - x,
+			height: (self.y + self.height).max(other.y + other.height) - y,
+		}
+	}
+}
+
+/// The part of a frame that changed, drawn and ready to be encoded
+pub struct Rendered {
+	/// What's in `region`
+	pub image: RgbaImage,
+	/// The same with the cursor blinked off, if it blinks
+	pub blinked: Option<RgbaImage>,
+	/// In frames at the output's framerate
+	pub duration: u32,
+	/// Where the frame differs from the one before, everything else stays as it was
+	pub region: Region,
+}
+
+pub fn render(rasterizer: &mut Rasterizer, frame: &Frame) -> Rendered {
+	let mut damage = frame.damage.clone();
+	// Every frame restarts the cursor's blink, so it's always redrawn
+	if let Some(cursor) = frame.screen.cursor {
+		damage.add(cursor.row);
+	}
+	let region = rasterizer.damage_region(&damage);
+
+	let blinks = frame.screen.cursor.is_some_and(|cursor| cursor.blinking);
+	Rendered {
+		image: rasterizer.render(&frame.screen, true, region),
+		blinked: blinks.then(|| rasterizer.render(&frame.screen, false, region)),
+		duration: frame.duration,
+		region,
+	}
+}
+
+/// Render `frames` on `jobs` threads and hand them to `sink` in order.
+///
+/// Every frame only depends on its own screen, so each thread draws with its
+/// own copy of `rasterizer`. Frames are taken as they come and only a few are
+/// rendered ahead of the one `sink` is waiting for, so memory stays flat
+/// however long the tape is.
+pub fn render_all(
+	rasterizer: &Rasterizer,
+	frames: impl Iterator<Item = Frame>,
+	jobs: usize,
+	mut sink: impl FnMut(Rendered) -> Result<()>,
+) -> Result<()> {
+	let jobs = jobs.max(1);
+	let ahead = jobs * 2;
+	let (job_tx, job_rx) = mpsc::channel::<(usize, Frame)>();
+	let job_rx = Mutex::new(job_rx);
+
+	thread::scope(|scope| {
+		// Owned by this closure, so the queue closes as soon as it returns
+		let job_tx = job_tx;
+		let (done_tx, done_rx) = mpsc::channel();
+
+		for _ in 0..jobs {
+			let mut rasterizer = rasterizer.clone();
+			let job_rx = &job_rx;
+			let done_tx = done_tx.clone();
+			scope.spawn(move || loop {
+				// Only hold the lock while waiting, not while rendering
+				let job = job_rx.lock().unwrap().recv();
+				// The queue closes once everything has been handed out
+				let Ok((i, frame)) = job else { break };
+				if done_tx.send((i, render(&mut rasterizer, &frame))).is_err() {
+					break;
+				}
+			});
+		}
+		drop(done_tx);
+
+		let mut frames = frames.enumerate().fuse();
+		let mut queued = 0;
+		let mut next = 0;
+		let mut pending = BTreeMap::new();
+		loop {
+			while queued < next + ahead
+				&& let Some(job) = frames.next()
+			{
+				job_tx.send(job)?;
+				queued += 1;
+			}
+			if next == queued {
+				break;
+			}
+
+			// Only fails if a renderer panicked, which the scope passes on
+			let Ok((i, rendered)) = done_rx.recv() else { break };
+			pending.insert(i, rendered);
+			while let Some(rendered) = pending.remove(&next) {
+				sink(rendered)?;
+				next += 1;
+			}
+		}
+
+		Ok(())
+	})
+}
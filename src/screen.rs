@@ -0,0 +1,299 @@
+use alacritty_terminal::Term;
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::TermDamage;
+use alacritty_terminal::term::cell::{self, Flags};
+use alacritty_terminal::vte::ansi::{CursorShape, NamedColor};
+use dvd_render::image::Rgba;
+use std::time::Duration;
+use crate::theme::{Palette, to_rgba};
+
+/// One cell of a captured screen, with its colors already resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+	pub c: char,
+	pub fg: Rgba<u8>,
+	pub bg: Rgba<u8>,
+	/// Color of any underline, `None` to use `fg`
+	pub underline: Option<Rgba<u8>>,
+	pub flags: Flags,
+	/// Combining characters drawn over `c`
+	pub zerowidth: Vec<char>,
+}
+
+/// How long a blinking cursor stays on, and then off
+pub const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+
+/// Where the cursor is and how the application wants it drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+	pub column: usize,
+	pub row: usize,
+	pub shape: CursorShape,
+	pub color: Rgba<u8>,
+	pub blinking: bool,
+}
+
+/// Which rows of a screen changed since the one before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Damage {
+	rows: Vec<bool>,
+}
+
+impl Damage {
+	pub fn none(rows: usize) -> Self {
+		Self { rows: vec![false; rows] }
+	}
+
+	pub fn full(rows: usize) -> Self {
+		Self { rows: vec![true; rows] }
+	}
+
+	pub fn add(&mut self, row: usize) {
+		if let Some(damaged) = self.rows.get_mut(row) {
+			*damaged = true;
+		}
+	}
+
+	/// Add everything `other` damaged, for screens that are skipped over
+	pub fn merge(&mut self, other: &Damage) {
+		for (damaged, other) in self.rows.iter_mut().zip(&other.rows) {
+			*damaged |= other;
+		}
+	}
+
+	pub fn rows(&self) -> impl Iterator<Item = usize> + '_ {
+		self.rows.iter().enumerate().filter(|(_, damaged)| **damaged).map(|(row, _)| row)
+	}
+
+	/// The first and last damaged rows
+	pub fn bounds(&self) -> Option<(usize, usize)> {
+		Some((self.rows().next()?, self.rows().last()?))
+	}
+
+	/// Append the damage to `out`, for [`Damage::decode`] to read back
+	pub fn encode(&self, out: &mut Vec<u8>) {
+		out.extend_from_slice(&(self.rows.len() as u32).to_le_bytes());
+		out.extend(self.rows.iter().map(|&damaged| damaged as u8));
+	}
+
+	pub fn decode(bytes: &mut &[u8]) -> Option<Self> {
+		let rows = u32::from_le_bytes(take(bytes)?) as usize;
+		let (rows, rest) = bytes.split_at_checked(rows)?;
+		*bytes = rest;
+		Some(Self { rows: rows.iter().map(|&damaged| damaged != 0).collect() })
+	}
+}
+
+/// Everything visible on the terminal at one moment, independent of the
+/// emulator so it can be kept around and rendered later
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+	pub columns: usize,
+	pub rows: usize,
+	/// `None` while the application hides the cursor
+	pub cursor: Option<Cursor>,
+	cells: Vec<Cell>,
+}
+
+impl Screen {
+	pub fn capture<T: EventListener>(term: &Term<T>, palette: &Palette) -> Self {
+		let palette = palette.with_overrides(term.colors());
+		let grid = term.grid();
+		let columns = grid.columns();
+		let rows = grid.screen_lines();
+
+		let cells = grid.display_iter().map(|indexed| convert(indexed.cell, &palette)).collect();
+		Self {
+			columns,
+			rows,
+			cursor: cursor(term, &palette),
+			cells,
+		}
+	}
+
+	/// Catch up with the terminal by re-reading only the rows it has redrawn
+	/// since it was last asked, returning which rows those were
+	pub fn update<T: EventListener>(&mut self, term: &mut Term<T>, palette: &Palette) -> Damage {
+		let mut damage = match term.damage() {
+			TermDamage::Full => Damage::full(self.rows),
+			TermDamage::Partial(lines) => {
+				let mut damage = Damage::none(self.rows);
+				for line in lines {
+					damage.add(line.line);
+				}
+				damage
+			}
+		};
+		term.reset_damage();
+
+		let palette = palette.with_overrides(term.colors());
+		let grid = term.grid();
+		let offset = grid.display_offset() as i32;
+		for row in damage.rows() {
+			let line = &grid[Line(row as i32 - offset)];
+			for column in 0..self.columns {
+				self.cells[row * self.columns + column] = convert(&line[Column(column)], &palette);
+			}
+		}
+
+		// Changing how the cursor looks doesn't damage anything in the terminal
+		let cursor = cursor(term, &palette);
+		if cursor != self.cursor {
+			for cursor in [self.cursor, cursor].into_iter().flatten() {
+				damage.add(cursor.row);
+			}
+			self.cursor = cursor;
+		}
+
+		damage
+	}
+
+	pub fn lines(&self) -> impl Iterator<Item = &[Cell]> {
+		self.cells.chunks(self.columns)
+	}
+
+	/// The text of each line, without trailing blanks
+	pub fn text(&self) -> Vec<String> {
+		self.lines()
+			.map(|line| {
+				let text: String = line
+					.iter()
+					.filter(|cell| !cell.flags.contains(Flags::WIDE_CHAR_SPACER))
+					.map(|cell| cell.c)
+					.collect();
+				text.trim_end().to_string()
+			})
+			.collect()
+	}
+
+	pub fn cell(&self, column: usize, row: usize) -> Option<&Cell> {
+		if column >= self.columns {
+			return None;
+		}
+		self.cells.get(row * self.columns + column)
+	}
+
+	/// Append the screen to `out`, for [`Screen::decode`] to read back
+	pub fn encode(&self, out: &mut Vec<u8>) {
+		let u32 = |out: &mut Vec<u8>, value: u32| out.extend_from_slice(&value.to_le_bytes());
+		u32(out, self.columns as u32);
+		u32(out, self.rows as u32);
+		match self.cursor {
+			Some(cursor) => {
+				out.push(1);
+				u32(out, cursor.column as u32);
+				u32(out, cursor.row as u32);
+				out.push(CURSOR_SHAPES.iter().position(|&shape| shape == cursor.shape).unwrap() as u8);
+				out.extend_from_slice(&cursor.color.0);
+				out.push(cursor.blinking as u8);
+			}
+			None => out.push(0),
+		}
+
+		for cell in &self.cells {
+			u32(out, cell.c as u32);
+			out.extend_from_slice(&cell.fg.0);
+			out.extend_from_slice(&cell.bg.0);
+			match cell.underline {
+				Some(color) => {
+					out.push(1);
+					out.extend_from_slice(&color.0);
+				}
+				None => out.push(0),
+			}
+			out.extend_from_slice(&cell.flags.bits().to_le_bytes());
+			u32(out, cell.zerowidth.len() as u32);
+			for &c in &cell.zerowidth {
+				u32(out, c as u32);
+			}
+		}
+	}
+
+	/// Read back a screen written by [`Screen::encode`], `None` if it's cut
+	/// short or garbled
+	pub fn decode(bytes: &mut &[u8]) -> Option<Self> {
+		let u32 = |bytes: &mut &[u8]| take(bytes).map(u32::from_le_bytes);
+		let byte = |bytes: &mut &[u8]| take::<1>(bytes).map(|[byte]| byte);
+		let char = |bytes: &mut &[u8]| u32(bytes).and_then(char::from_u32);
+		let color = |bytes: &mut &[u8]| take(bytes).map(Rgba);
+
+		let columns = u32(bytes)? as usize;
+		let rows = u32(bytes)? as usize;
+		let cursor = match byte(bytes)? {
+			0 => None,
+			_ => Some(Cursor {
+				column: u32(bytes)? as usize,
+				row: u32(bytes)? as usize,
+				shape: *CURSOR_SHAPES.get(byte(bytes)? as usize)?,
+				color: color(bytes)?,
+				blinking: byte(bytes)? != 0,
+			}),
+		};
+
+		let mut cells = Vec::with_capacity(columns * rows);
+		for _ in 0..columns * rows {
+			let c = char(bytes)?;
+			let fg = color(bytes)?;
+			let bg = color(bytes)?;
+			let underline = match byte(bytes)? {
+				0 => None,
+				_ => Some(color(bytes)?),
+			};
+			let flags = Flags::from_bits_retain(u16::from_le_bytes(take(bytes)?));
+			let zerowidth = (0..u32(bytes)?).map(|_| char(bytes)).collect::<Option<_>>()?;
+			cells.push(Cell { c, fg, bg, underline, flags, zerowidth });
+		}
+
+		Some(Self { columns, rows, cursor, cells })
+	}
+}
+
+/// Every cursor shape, numbered by position for [`Screen::encode`]
+const CURSOR_SHAPES: [CursorShape; 5] = [
+	CursorShape::Block,
+	CursorShape::Underline,
+	CursorShape::Beam,
+	CursorShape::HollowBlock,
+	CursorShape::Hidden,
+];
+
+/// The next `N` bytes, moving past them
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+	let (head, rest) = bytes.split_first_chunk::<N>()?;
+	*bytes = rest;
+	Some(*head)
+}
+
+fn convert(cell: &cell::Cell, palette: &Palette) -> Cell {
+	let (fg, bg) = palette.cell_colors(cell);
+	let underline = cell
+		.underline_color()
+		.map(|color| to_rgba(palette.resolve_fg(color, cell.flags)));
+
+	Cell {
+		c: cell.c,
+		fg,
+		bg,
+		underline,
+		flags: cell.flags,
+		zerowidth: cell.zerowidth().map(<[char]>::to_vec).unwrap_or_default(),
+	}
+}
+
+fn cursor<T: EventListener>(term: &Term<T>, palette: &Palette) -> Option<Cursor> {
+	let content = term.renderable_content();
+	let shape = match content.cursor.shape {
+		CursorShape::Hidden => None,
+		_ if !term.is_focused => Some(CursorShape::HollowBlock),
+		shape => Some(shape),
+	};
+	shape.map(|shape| Cursor {
+		column: content.cursor.point.column.0,
+		row: (content.cursor.point.line.0 + content.display_offset as i32) as usize,
+		shape,
+		color: to_rgba(palette.get(NamedColor::Cursor)),
+		blinking: term.cursor_style().blinking,
+	})
+}
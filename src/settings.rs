@@ -0,0 +1,172 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::time::Duration;
+use crate::parser::{Commands, LoopOffset, Setting};
+use crate::theme::Theme;
+use crate::themes;
+
+/// Everything a tape can configure with `Set`, with the defaults used when it doesn't.
+///
+/// Settings apply to the whole recording no matter where in the tape they
+/// appear, the last one wins.
+#[derive(Debug, Clone)]
+pub struct Settings {
+	/// Font size in pixels
+	pub font_size: u32,
+	/// An installed family or a path to a font file, `None` for the bundled font
+	pub font_family: Option<String>,
+	/// Whether to shape text so the font's ligatures show up
+	pub font_ligatures: bool,
+	/// Output width in pixels, including padding
+	pub width: u32,
+	/// Output height in pixels, including padding
+	pub height: u32,
+	/// Exact terminal width in cells, overriding `width`
+	pub columns: Option<u32>,
+	/// Exact terminal height in cells, overriding `height`
+	pub rows: Option<u32>,
+	/// Extra pixels between neighbouring cells
+	pub letter_spacing: f32,
+	/// Multiplier on the font's natural line height
+	pub line_height: f32,
+	/// Pixels between the terminal and the edge of the output
+	pub padding: u32,
+	pub theme: Theme,
+	/// Frames per second of the output
+	pub framerate: u32,
+	/// How long the last screen stays up after the tape finishes
+	pub tail: Duration,
+	/// How long each keystroke of `Type` takes
+	pub typing_speed: Duration,
+	/// How much keystrokes vary around `typing_speed`, 0 for not at all
+	pub typing_jitter: f32,
+	/// Chance of mistyping each letter or digit, 0 for never
+	pub typo_rate: f32,
+	/// Seeds the typing variation, so a tape always records the same way
+	pub seed: u64,
+	/// How much faster than real time the output plays
+	pub playback_speed: f32,
+	/// Where a looping output starts
+	pub loop_offset: LoopOffset,
+	/// How many times a GIF or SVG plays, 0 for forever
+	pub loop_count: u32,
+	pub inherit_env: bool,
+	/// Whether SVG output carries the glyphs it uses, so it looks the same
+	/// where the font isn't installed
+	pub embed_font: bool,
+	/// Whether CSV output includes the colors along each row
+	pub csv_colors: bool,
+	/// Whether the cursor blinks, unless the application asks for a steady one
+	pub cursor_blink: bool,
+	/// How long `Wait` gives up after
+	pub wait_timeout: Duration,
+	/// What `Wait` looks for when not given a pattern
+	pub wait_pattern: Regex,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			font_size: 22,
+			font_family: None,
+			font_ligatures: true,
+			width: 1200,
+			height: 600,
+			columns: None,
+			rows: None,
+			letter_spacing: 0.0,
+			line_height: 1.0,
+			padding: 60,
+			theme: Theme::default(),
+			framerate: 50,
+			tail: Duration::from_secs(1),
+			typing_speed: Duration::from_millis(50),
+			typing_jitter: 0.0,
+			typo_rate: 0.0,
+			seed: 0,
+			playback_speed: 1.0,
+			loop_offset: LoopOffset::Frames(0),
+			loop_count: 0,
+			inherit_env: true,
+			embed_font: false,
+			csv_colors: false,
+			cursor_blink: true,
+			wait_timeout: Duration::from_secs(15),
+			// A typical shell prompt
+			wait_pattern: Regex::new(">$").unwrap(),
+		}
+	}
+}
+
+impl Settings {
+	pub fn from_commands(commands: &[Commands]) -> Result<Self> {
+		let mut settings = Self::default();
+
+		for command in commands {
+			if let Commands::Set(set_cmd) = command {
+				settings.apply(&set_cmd.setting)?;
+			}
+		}
+
+		Ok(settings)
+	}
+
+	fn apply(&mut self, setting: &Setting) -> Result<()> {
+		match *setting {
+			Setting::FontSize(0) => return Err(anyhow!("Set FontSize needs to be at least 1")),
+			Setting::FontSize(size) => self.font_size = size,
+			Setting::FontFamily(ref family) => self.font_family = Some(family.clone()),
+			Setting::FontLigatures(ligatures) => self.font_ligatures = ligatures,
+			Setting::Width(width) => self.width = width,
+			Setting::Height(height) => self.height = height,
+			// The pty measures itself in 16 bits
+			Setting::Columns(columns) if !(1..=u16::MAX as u32).contains(&columns) => {
+				return Err(anyhow!("Set Columns needs to be between 1 and {}, got {}", u16::MAX, columns));
+			}
+			Setting::Columns(columns) => self.columns = Some(columns),
+			Setting::Rows(rows) if !(1..=u16::MAX as u32).contains(&rows) => {
+				return Err(anyhow!("Set Rows needs to be between 1 and {}, got {}", u16::MAX, rows));
+			}
+			Setting::Rows(rows) => self.rows = Some(rows),
+			Setting::LetterSpacing(spacing) => self.letter_spacing = spacing,
+			Setting::LineHeight(height) if height <= 0.0 => {
+				return Err(anyhow!("Set LineHeight needs to be more than 0, got {}", height));
+			}
+			Setting::LineHeight(height) => self.line_height = height,
+			Setting::Padding(padding) => self.padding = padding,
+			Setting::Theme(ref theme) => self.theme = themes::resolve(theme)?,
+			Setting::Framerate(0) => return Err(anyhow!("Set Framerate needs at least 1 frame per second")),
+			Setting::Framerate(framerate) => self.framerate = framerate,
+			Setting::Tail(tail) => self.tail = tail,
+			Setting::TypingSpeed(speed) => self.typing_speed = speed,
+			Setting::TypingJitter(jitter) if jitter < 0.0 => {
+				return Err(anyhow!("Set TypingJitter can't be negative, got {}", jitter));
+			}
+			Setting::TypingJitter(jitter) => self.typing_jitter = jitter,
+			Setting::TypoRate(rate) if !(0.0..=1.0).contains(&rate) => {
+				return Err(anyhow!("Set TypoRate needs to be between 0 and 1, got {}", rate));
+			}
+			Setting::TypoRate(rate) => self.typo_rate = rate,
+			Setting::Seed(seed) => self.seed = seed,
+			Setting::PlaybackSpeed(speed) if speed <= 0.0 => {
+				return Err(anyhow!("Set PlaybackSpeed needs to be more than 0, got {}", speed));
+			}
+			Setting::PlaybackSpeed(speed) => self.playback_speed = speed,
+			Setting::LoopOffset(offset) => self.loop_offset = offset,
+			// GIFs store the number of repeats after the first play in 16 bits
+			Setting::LoopCount(count) if count > u16::MAX as u32 + 1 => {
+				return Err(anyhow!("Set LoopCount can be at most {}, got {}", u16::MAX as u32 + 1, count));
+			}
+			Setting::LoopCount(count) => self.loop_count = count,
+			Setting::InheritEnv(inherit) => self.inherit_env = inherit,
+			Setting::EmbedFont(embed) => self.embed_font = embed,
+			Setting::CsvColors(colors) => self.csv_colors = colors,
+			Setting::CursorBlink(blink) => self.cursor_blink = blink,
+			Setting::WaitTimeout(timeout) => self.wait_timeout = timeout,
+			Setting::WaitPattern(ref pattern) => self.wait_pattern = Regex::new(pattern)?,
+			_ => {}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,101 @@
+use dvd_render::ab_glyph::Font;
+use rustybuzz::{Direction, UnicodeBuffer};
+use std::collections::HashMap;
+use crate::fonts::{Fonts, Style};
+
+/// A glyph placed by the shaper
+#[derive(Debug, Clone, Copy)]
+pub struct ShapedGlyph {
+	/// The cell, within the run, of the text this glyph was made from
+	pub cell: usize,
+	pub id: u16,
+	/// Offset from that cell's origin, in font units
+	pub x: i32,
+	pub y: i32,
+}
+
+/// How many runs are remembered before the oldest are let go
+const CACHE_SIZE: usize = 4096;
+
+/// Shapes runs of same-styled text with the font's OpenType features, so
+/// that ligatures and contextual alternates show up.
+///
+/// Every glyph stays anchored to the cell its text came from, so the grid
+/// stays aligned whatever the font does. The same lines come up frame after
+/// frame, so results are remembered, though only the recently used ones
+/// since output that keeps changing would otherwise fill memory.
+#[derive(Default, Clone)]
+pub struct Shaper {
+	cache: HashMap<(Style, String), Option<Vec<ShapedGlyph>>>,
+	/// What was cached before `cache` last filled up, kept for anything
+	/// still in use to be moved back over
+	previous: HashMap<(Style, String), Option<Vec<ShapedGlyph>>>,
+}
+
+impl Shaper {
+	/// Shape `text`, one character per cell, or `None` when the font draws it
+	/// exactly as it would one character at a time
+	pub fn shape(&mut self, fonts: &Fonts, style: Style, text: &str) -> Option<Vec<ShapedGlyph>> {
+		let key = (style, text.to_string());
+		if let Some(glyphs) = self.cache.get(&key) {
+			return glyphs.clone();
+		}
+
+		let glyphs = match self.previous.remove(&key) {
+			Some(glyphs) => glyphs,
+			None => shape_run(fonts, style, text),
+		};
+		if self.cache.len() >= CACHE_SIZE {
+			self.previous = std::mem::take(&mut self.cache);
+		}
+		self.cache.insert(key, glyphs.clone());
+		glyphs
+	}
+}
+
+fn shape_run(fonts: &Fonts, style: Style, text: &str) -> Option<Vec<ShapedGlyph>> {
+	let font = fonts.face(style).font;
+	let face = font.shaping_face()?;
+
+	let mut buffer = UnicodeBuffer::new();
+	buffer.push_str(text);
+	// Terminals lay text out left to right, whatever the script
+	buffer.set_direction(Direction::LeftToRight);
+	let output = rustybuzz::shape(&face, &[], buffer);
+
+	// Clusters are byte offsets, find the cell each one starts in
+	let starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+	let chars: Vec<char> = text.chars().collect();
+	let cell_of = |cluster: u32| match starts.binary_search(&(cluster as usize)) {
+		Ok(cell) => cell,
+		Err(cell) => cell.saturating_sub(1),
+	};
+
+	let mut changed = output.len() != chars.len();
+	let mut glyphs = Vec::with_capacity(output.len());
+	let mut pen = 0;
+	let mut cluster = None;
+	let mut cluster_pen = 0;
+
+	for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+		let cell = cell_of(info.cluster);
+		if cluster != Some(info.cluster) {
+			cluster = Some(info.cluster);
+			cluster_pen = pen;
+		}
+
+		changed |= info.glyph_id != font.glyph_id(chars[cell]).0 as u32
+			|| position.x_offset != 0
+			|| position.y_offset != 0;
+
+		glyphs.push(ShapedGlyph {
+			cell,
+			id: info.glyph_id as u16,
+			x: pen - cluster_pen + position.x_offset,
+			y: position.y_offset,
+		});
+		pen += position.x_advance;
+	}
+
+	changed.then_some(glyphs)
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use crate::record::Frame;
+use crate::screen::{Damage, Screen};
+
+/// Frames kept in a temporary file rather than in memory, for when all of a
+/// recording has to be in before any of it can be written out.
+///
+/// Each frame is stored as its length followed by its duration, screen and
+/// damage, and the file is gone as soon as it's closed.
+pub struct Spill {
+	file: BufWriter<File>,
+	/// Where each frame starts in the file, and how long it lasts
+	frames: Vec<(u64, u32)>,
+	end: u64,
+}
+
+impl Spill {
+	pub fn new() -> Result<Self> {
+		let file = tempfile::tempfile().context("Failed to create a temporary file for frames")?;
+		Ok(Self {
+			file: BufWriter::new(file),
+			frames: Vec::new(),
+			end: 0,
+		})
+	}
+
+	pub fn push(&mut self, frame: &Frame) -> Result<()> {
+		let mut bytes = frame.duration.to_le_bytes().to_vec();
+		frame.screen.encode(&mut bytes);
+		frame.damage.encode(&mut bytes);
+
+		self.file.write_all(&(bytes.len() as u64).to_le_bytes())?;
+		self.file.write_all(&bytes)?;
+		self.frames.push((self.end, frame.duration));
+		self.end += 8 + bytes.len() as u64;
+		Ok(())
+	}
+
+	/// How long each frame lasts, in the order they were pushed
+	pub fn durations(&self) -> impl Iterator<Item = u32> + '_ {
+		self.frames.iter().map(|&(_, duration)| duration)
+	}
+
+	/// Read frames back, visiting them in `order` by the index they were
+	/// pushed at
+	pub fn replay(self, order: impl Iterator<Item = usize>) -> Result<impl Iterator<Item = Frame>> {
+		let file = self.file.into_inner().map_err(|e| e.into_error())?;
+		let mut file = BufReader::new(file);
+		let offsets = self.frames;
+		// Only seek when the order jumps, so reading straight through stays buffered
+		let mut position = None;
+
+		Ok(order.map(move |index| {
+			if position != Some(index) {
+				file.seek(SeekFrom::Start(offsets[index].0))
+					.expect("Failed to seek in the temporary file for frames");
+			}
+			position = Some(index + 1);
+			read_frame(&mut file).expect("Failed to read back a frame from the temporary file")
+		}))
+	}
+}
+
+fn read_frame(file: &mut impl Read) -> Result<Frame> {
+	let mut length = [0; 8];
+	file.read_exact(&mut length)?;
+	let mut bytes = vec![0; u64::from_le_bytes(length) as usize];
+	file.read_exact(&mut bytes)?;
+
+	let mut bytes = bytes.as_slice();
+	let decoded = (|| {
+		let (duration, rest) = bytes.split_first_chunk::<4>()?;
+		bytes = rest;
+		Some(Frame {
+			duration: u32::from_le_bytes(*duration),
+			screen: Screen::decode(&mut bytes)?,
+			damage: Damage::decode(&mut bytes)?,
+		})
+	})();
+	decoded.context("A frame in the temporary file is garbled")
+}
@@ -0,0 +1,488 @@
+use anyhow::{Context, Result};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::vte::ansi::CursorShape;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use dvd_render::ab_glyph::{Font, PxScale, ScaleFont};
+use dvd_render::image::Rgba;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::fonts::{FontFile, Fonts, Style};
+use crate::layout::Layout;
+use crate::record::Frame;
+use crate::screen::{CURSOR_BLINK_INTERVAL, Cell, Cursor, Screen};
+use crate::settings::Settings;
+use crate::theme::to_rgba;
+
+/// The flags that change how text itself looks
+const TEXT_FLAGS: Flags = Flags::BOLD
+	.union(Flags::ITALIC)
+	.union(Flags::ALL_UNDERLINES)
+	.union(Flags::STRIKEOUT);
+
+/// How many distinct lines are remembered before the oldest are let go. One
+/// that shows up again after that is simply defined a second time.
+const LINE_CACHE_SIZE: usize = 4096;
+
+/// Writes frames out as an animated SVG, with the screen as real text.
+///
+/// Every frame is laid out below the one before it and a CSS animation
+/// slides each into view in turn. Lines are defined once and reused by
+/// every frame that shows them, which is what keeps the files small, since
+/// most of a terminal stays the same from one frame to the next.
+pub struct SvgWriter {
+	file: BufWriter<File>,
+	fonts: Fonts,
+	layout: Layout,
+	embed_font: bool,
+	/// CSS font size, which measures the em rather than the line
+	font_size: f32,
+	/// What CSS has to add to the font's own advance to fill a cell
+	letter_spacing: f32,
+	/// Distance from the top of a cell to the text's baseline
+	baseline: f32,
+	/// Thickness of the thinner cursors
+	stroke: f32,
+	background: Rgba<u8>,
+	framerate: u32,
+	loop_count: u32,
+	/// Class of every color used, numbered in order of first use
+	colors: HashMap<Rgba<u8>, usize>,
+	/// Id of recently drawn lines, by their markup
+	lines: HashMap<String, usize>,
+	/// What was in `lines` before it last filled up, kept for anything still
+	/// in use to be moved back over
+	previous_lines: HashMap<String, usize>,
+	/// How many lines have been defined, forgotten ones included
+	line_count: usize,
+	/// Every line's definition, kept on disk like the body
+	defs: BufWriter<File>,
+	/// Every frame's lines and cursor, which are most of the file, kept on
+	/// disk until the header before them is known
+	body: BufWriter<File>,
+	/// When each frame starts, in frames at the output's framerate
+	starts: Vec<u32>,
+	elapsed: u32,
+	/// Everything drawn, so the embedded fonts can be cut down to it
+	chars: HashSet<(char, Style)>,
+}
+
+impl SvgWriter {
+	pub fn new(output: &Path, layout: &Layout, fonts: &Fonts, settings: &Settings) -> Result<Self> {
+		let file = File::create(output).with_context(|| format!("Failed to create {}", output.display()))?;
+
+		let regular = &fonts.regular;
+		let scale = PxScale::from(settings.font_size as f32);
+		let scaled = regular.as_scaled(scale);
+		let (ascent, descent) = (scaled.ascent(), scaled.descent());
+		let units_per_em = regular.units_per_em().unwrap_or(regular.height_unscaled());
+
+		Ok(Self {
+			file: BufWriter::new(file),
+			fonts: fonts.clone(),
+			layout: *layout,
+			embed_font: settings.embed_font,
+			font_size: settings.font_size as f32 * units_per_em / regular.height_unscaled(),
+			letter_spacing: layout.cell_width - scaled.h_advance(scaled.glyph_id('0')),
+			// Extra line height is split evenly above and below the text
+			baseline: (layout.cell_height - (ascent - descent)) / 2.0 + ascent,
+			stroke: (settings.font_size as f32 / 15.0).round().max(1.0),
+			background: to_rgba(settings.theme.background),
+			framerate: settings.framerate,
+			loop_count: settings.loop_count,
+			colors: HashMap::new(),
+			lines: HashMap::new(),
+			previous_lines: HashMap::new(),
+			line_count: 0,
+			defs: BufWriter::new(tempfile::tempfile().context("Failed to create a temporary file for the SVG")?),
+			body: BufWriter::new(tempfile::tempfile().context("Failed to create a temporary file for the SVG")?),
+			starts: Vec::new(),
+			elapsed: 0,
+			chars: HashSet::new(),
+		})
+	}
+
+	pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+		let top = self.starts.len() as f32 * self.screen_height();
+		self.starts.push(self.elapsed);
+		self.elapsed += frame.duration;
+
+		let screen = &frame.screen;
+		for (row, line) in screen.lines().enumerate() {
+			let markup = self.line(line);
+			if markup.is_empty() {
+				continue;
+			}
+			let id = self.define(markup)?;
+			let y = top + row as f32 * self.layout.cell_height;
+			write!(self.body, r##"<use href="#l{}" y="{}"/>"##, id, px(y))?;
+		}
+
+		if let Some(cursor) = screen.cursor {
+			self.cursor(screen, &cursor, top)?;
+		}
+		Ok(())
+	}
+
+	/// The id of the line drawn by `markup`, defining it if it isn't known
+	fn define(&mut self, markup: String) -> Result<usize> {
+		if let Some(&id) = self.lines.get(&markup) {
+			return Ok(id);
+		}
+
+		let id = match self.previous_lines.remove(&markup) {
+			Some(id) => id,
+			None => {
+				let id = self.line_count;
+				self.line_count += 1;
+				write!(self.defs, r#"<g id="l{}">{}</g>"#, id, markup)?;
+				id
+			}
+		};
+		if self.lines.len() >= LINE_CACHE_SIZE {
+			self.previous_lines = std::mem::take(&mut self.lines);
+		}
+		self.lines.insert(markup, id);
+		Ok(id)
+	}
+
+	pub fn finish(mut self) -> Result<()> {
+		let (width, height) = (self.layout.width(), self.layout.height());
+		let mut style = self.fonts_css()?;
+
+		let family = if self.embed_font {
+			let mut families = vec!["dvd".to_string()];
+			families.extend((0..self.fonts.fallback.len()).map(|i| format!("dvd-fallback-{}", i)));
+			families.join(",")
+		} else {
+			format!("'{}'", self.fonts.family.replace('\'', "\\'"))
+		};
+		let _ = write!(
+			style,
+			"text{{font-family:{},monospace;font-size:{}px;letter-spacing:{}px;white-space:pre}}",
+			family,
+			px(self.font_size),
+			px(self.letter_spacing),
+		);
+		style.push_str("rect{shape-rendering:crispEdges}");
+		style.push_str(".b{font-weight:bold}.i{font-style:italic}");
+		style.push_str(".u{text-decoration:underline}.s{text-decoration:line-through}");
+		style.push_str(".u.s{text-decoration:underline line-through}");
+
+		let mut colors: Vec<_> = self.colors.iter().collect();
+		colors.sort_by_key(|(_, class)| **class);
+		for (&color, class) in colors {
+			let _ = write!(style, ".c{}{{fill:{}}}", class, hex(color));
+		}
+
+		// Each frame holds until the next one starts
+		let total = self.elapsed.max(1);
+		let iterations = match self.loop_count {
+			0 => "infinite".to_string(),
+			count => format!("{} forwards", count),
+		};
+		let _ = write!(
+			style,
+			".screen{{animation:play {}s steps(1,end) {}}}@keyframes play{{",
+			px(total as f32 / self.framerate as f32),
+			iterations,
+		);
+		for (i, start) in self.starts.iter().enumerate() {
+			let at = *start as f32 * 100.0 / total as f32;
+			let offset = -(i as f32) * self.screen_height();
+			let _ = write!(style, "{}%{{transform:translateY({}px)}}", pct(at), px(offset));
+		}
+		let last = -(self.starts.len().saturating_sub(1) as f32) * self.screen_height();
+		let _ = write!(style, "100%{{transform:translateY({}px)}}}}", px(last));
+		let _ = write!(
+			style,
+			".blink{{animation:blink {}s steps(1,end) infinite}}@keyframes blink{{50%{{opacity:0}}}}",
+			px(CURSOR_BLINK_INTERVAL.as_secs_f32() * 2.0),
+		);
+
+		let screen_width = self.layout.columns as f32 * self.layout.cell_width;
+		let screen_height = self.screen_height();
+		let file = &mut self.file;
+		write!(
+			file,
+			r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+			w = width,
+			h = height,
+		)?;
+		write!(file, "<style>{}</style>", style)?;
+		write!(file, "<defs>")?;
+		copy_back(self.defs, file)?;
+		write!(file, "</defs>")?;
+		write!(file, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(self.background))?;
+		// The nested viewport hides every frame but the one sliding through it
+		write!(
+			file,
+			r#"<svg x="{p}" y="{p}" width="{}" height="{}"><g class="screen">"#,
+			px(screen_width),
+			px(screen_height),
+			p = self.layout.padding,
+		)?;
+		copy_back(self.body, file)?;
+		write!(file, "</g></svg></svg>")?;
+		self.file.flush()?;
+		Ok(())
+	}
+
+	fn screen_height(&self) -> f32 {
+		self.layout.rows as f32 * self.layout.cell_height
+	}
+
+	fn color(&mut self, color: Rgba<u8>) -> usize {
+		let next = self.colors.len();
+		*self.colors.entry(color).or_insert(next)
+	}
+
+	/// One line's backgrounds and text, relative to the top left of the line.
+	/// Empty for a blank line.
+	fn line(&mut self, line: &[Cell]) -> String {
+		let (cell_width, cell_height) = (self.layout.cell_width, self.layout.cell_height);
+		let mut markup = String::new();
+
+		let mut column = 0;
+		while column < line.len() {
+			let bg = line[column].bg;
+			let end = column + line[column..].iter().take_while(|cell| cell.bg == bg).count();
+			if bg != self.background {
+				let class = self.color(bg);
+				let _ = write!(
+					markup,
+					r#"<rect x="{}" width="{}" height="{}" class="c{}"/>"#,
+					px(column as f32 * cell_width),
+					px((end - column) as f32 * cell_width),
+					px(cell_height),
+					class,
+				);
+			}
+			column = end;
+		}
+
+		let visible = |cell: &Cell| {
+			cell.c != ' ' || !cell.zerowidth.is_empty() || cell.flags.intersects(Flags::ALL_UNDERLINES | Flags::STRIKEOUT)
+		};
+		let Some(last) = line.iter().rposition(visible) else {
+			return markup;
+		};
+
+		let mut text = String::new();
+		let mut column = 0;
+		while column <= last {
+			let cell = &line[column];
+			if !visible(cell) || cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+				column += 1;
+				continue;
+			}
+
+			// A run keeps going for as long as the font's own advances keep
+			// it lined up with the cells
+			let mut run = String::new();
+			let mut end = column;
+			while end <= last {
+				let other = &line[end];
+				if other.flags.contains(Flags::WIDE_CHAR_SPACER)
+					|| other.fg != cell.fg
+					|| other.flags & TEXT_FLAGS != cell.flags & TEXT_FLAGS
+				{
+					break;
+				}
+				let style = Style::from_flags(other.flags);
+				for c in std::iter::once(other.c).chain(other.zerowidth.iter().copied()) {
+					self.chars.insert((c, style));
+					escape(&mut run, c);
+				}
+				end += 1;
+				if !other.c.is_ascii() {
+					break;
+				}
+			}
+
+			let classes = self.classes(cell.fg, cell.flags);
+			let _ = write!(
+				text,
+				r#"<tspan x="{}" class="{}">{}</tspan>"#,
+				px(column as f32 * cell_width),
+				classes,
+				run,
+			);
+			column = end;
+		}
+
+		let _ = write!(markup, r#"<text y="{}">{}</text>"#, px(self.baseline), text);
+		markup
+	}
+
+	fn classes(&mut self, color: Rgba<u8>, flags: Flags) -> String {
+		let mut classes = format!("c{}", self.color(color));
+		for (flag, class) in [
+			(Flags::BOLD, " b"),
+			(Flags::ITALIC, " i"),
+			(Flags::ALL_UNDERLINES, " u"),
+			(Flags::STRIKEOUT, " s"),
+		] {
+			if flags.intersects(flag) {
+				classes.push_str(class);
+			}
+		}
+		classes
+	}
+
+	fn cursor(&mut self, screen: &Screen, cursor: &Cursor, top: f32) -> Result<()> {
+		let Some(cell) = screen.cell(cursor.column, cursor.row) else {
+			return Ok(());
+		};
+		let (cell_width, cell_height) = (self.layout.cell_width, self.layout.cell_height);
+		let x = cursor.column as f32 * cell_width;
+		let y = top + cursor.row as f32 * cell_height;
+		// Cover both halves of a wide character
+		let width = if cell.flags.contains(Flags::WIDE_CHAR) { 2.0 * cell_width } else { cell_width };
+		let stroke = self.stroke;
+		let color = self.color(cursor.color);
+
+		let rect = |x: f32, y: f32, width: f32, height: f32| {
+			format!(
+				r#"<rect x="{}" y="{}" width="{}" height="{}" class="c{}"/>"#,
+				px(x),
+				px(y),
+				px(width),
+				px(height),
+				color,
+			)
+		};
+		let markup = match cursor.shape {
+			CursorShape::Block => {
+				// Redraw the character on top in its background color so it stays readable
+				let mut markup = rect(x, y, width, cell_height);
+				if cell.c != ' ' {
+					let mut text = String::new();
+					let style = Style::from_flags(cell.flags);
+					for c in std::iter::once(cell.c).chain(cell.zerowidth.iter().copied()) {
+						self.chars.insert((c, style));
+						escape(&mut text, c);
+					}
+					let _ = write!(
+						markup,
+						r#"<text x="{}" y="{}" class="{}">{}</text>"#,
+						px(x),
+						px(y + self.baseline),
+						self.classes(cell.bg, cell.flags),
+						text,
+					);
+				}
+				markup
+			}
+			CursorShape::Beam => rect(x, y, stroke, cell_height),
+			CursorShape::Underline => rect(x, y + cell_height - stroke, width, stroke),
+			CursorShape::HollowBlock => [
+				rect(x, y, width, stroke),
+				rect(x, y + cell_height - stroke, width, stroke),
+				rect(x, y, stroke, cell_height),
+				rect(x + width - stroke, y, stroke, cell_height),
+			]
+			.concat(),
+			CursorShape::Hidden => return Ok(()),
+		};
+
+		if cursor.blinking {
+			write!(self.body, r#"<g class="blink">{}</g>"#, markup)?;
+		} else {
+			self.body.write_all(markup.as_bytes())?;
+		}
+		Ok(())
+	}
+
+	/// `@font-face` rules carrying just the glyphs that were drawn, when
+	/// embedding fonts
+	fn fonts_css(&self) -> Result<String> {
+		let mut css = String::new();
+		if !self.embed_font {
+			return Ok(css);
+		}
+
+		let fonts = &self.fonts;
+		let mut faces: Vec<(&FontFile, String)> = vec![(&fonts.regular, "font-family:dvd".to_string())];
+		for (font, descriptors) in [
+			(&fonts.bold, "font-family:dvd;font-weight:bold"),
+			(&fonts.italic, "font-family:dvd;font-style:italic"),
+			(&fonts.bold_italic, "font-family:dvd;font-weight:bold;font-style:italic"),
+		] {
+			if let Some(font) = font {
+				faces.push((font, descriptors.to_string()));
+			}
+		}
+		for (i, font) in fonts.fallback.iter().enumerate() {
+			faces.push((font, format!("font-family:dvd-fallback-{}", i)));
+		}
+
+		// Glyph 0 is what gets drawn for anything missing
+		let mut glyphs: Vec<BTreeSet<u16>> = vec![BTreeSet::from([0]); faces.len()];
+		for &(c, style) in &self.chars {
+			let face = fonts.face_for(c, style);
+			if let Some(i) = faces.iter().position(|(font, _)| std::ptr::eq(*font, face.font)) {
+				glyphs[i].insert(face.font.glyph_id(c).0);
+			}
+		}
+
+		for ((font, descriptors), glyphs) in faces.iter().zip(glyphs) {
+			// Only the regular face is always needed, the rest can be left out entirely
+			if glyphs.len() == 1 && !std::ptr::eq(*font, &fonts.regular) {
+				continue;
+			}
+			let glyphs: Vec<u16> = glyphs.into_iter().collect();
+			let data = font.subset(&glyphs)?;
+			let _ = write!(
+				css,
+				"@font-face{{{};src:url(data:font/ttf;base64,{})}}",
+				descriptors,
+				BASE64.encode(data),
+			);
+		}
+		Ok(css)
+	}
+}
+
+/// Append everything written to a temporary file to `out`
+fn copy_back(temp: BufWriter<File>, out: &mut impl Write) -> Result<()> {
+	let mut temp = temp.into_inner().map_err(|e| e.into_error())?;
+	temp.seek(SeekFrom::Start(0))?;
+	io::copy(&mut temp, out)?;
+	Ok(())
+}
+
+/// A length with no more precision than anyone can see
+fn px(value: f32) -> String {
+	let text = format!("{:.2}", value);
+	let text = text.trim_end_matches('0').trim_end_matches('.');
+	match text {
+		"-0" => "0".to_string(),
+		text => text.to_string(),
+	}
+}
+
+/// A keyframe position, precise enough that frames don't blur together
+fn pct(value: f32) -> String {
+	let text = format!("{:.4}", value);
+	text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn hex(color: Rgba<u8>) -> String {
+	let Rgba([r, g, b, _]) = color;
+	format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape(text: &mut String, c: char) {
+	match c {
+		'&' => text.push_str("&amp;"),
+		'<' => text.push_str("&lt;"),
+		'>' => text.push_str("&gt;"),
+		// Not allowed in XML at all
+		c if c.is_control() => text.push(' '),
+		c => text.push(c),
+	}
+}
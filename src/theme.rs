@@ -0,0 +1,138 @@
+use alacritty_terminal::term::cell::{Cell, Flags};
+use alacritty_terminal::term::color::{COUNT, Colors};
+use alacritty_terminal::vte::ansi::{Color, NamedColor, Rgb};
+use dvd_render::image::Rgba;
+use crate::themes::DEFAULT_THEME;
+
+/// How much dimmed text is darkened, the same factor alacritty uses
+const DIM_FACTOR: f32 = 0.66;
+
+/// The colors a terminal is drawn with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+	pub foreground: Rgb,
+	pub background: Rgb,
+	pub cursor: Rgb,
+	/// The 16 ANSI colors, normal then bright
+	pub ansi: [Rgb; 16],
+}
+
+pub const fn rgb(hex: u32) -> Rgb {
+	Rgb {
+		r: (hex >> 16) as u8,
+		g: (hex >> 8) as u8,
+		b: hex as u8,
+	}
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		DEFAULT_THEME
+	}
+}
+
+fn dim(color: Rgb) -> Rgb {
+	let scale = |c: u8| (c as f32 * DIM_FACTOR) as u8;
+	Rgb {
+		r: scale(color.r),
+		g: scale(color.g),
+		b: scale(color.b),
+	}
+}
+
+pub fn to_rgba(color: Rgb) -> Rgba<u8> {
+	Rgba([color.r, color.g, color.b, 255])
+}
+
+/// Every color a cell can refer to, indexed the same way as alacritty's
+/// [`Colors`]: the 256 color palette followed by the named special colors.
+#[derive(Debug, Clone)]
+pub struct Palette([Rgb; COUNT]);
+
+impl Palette {
+	pub fn new(theme: &Theme) -> Self {
+		let mut colors = [Rgb::default(); COUNT];
+
+		colors[..16].copy_from_slice(&theme.ansi);
+
+		// 6x6x6 color cube
+		let level = |i: usize| if i == 0 { 0 } else { (i * 40 + 55) as u8 };
+		for i in 0..216 {
+			colors[16 + i] = Rgb {
+				r: level(i / 36),
+				g: level(i / 6 % 6),
+				b: level(i % 6),
+			};
+		}
+
+		// 24 step grayscale ramp
+		for i in 0..24 {
+			let value = (i * 10 + 8) as u8;
+			colors[232 + i] = Rgb { r: value, g: value, b: value };
+		}
+
+		colors[NamedColor::Foreground as usize] = theme.foreground;
+		colors[NamedColor::Background as usize] = theme.background;
+		colors[NamedColor::Cursor as usize] = theme.cursor;
+		for i in 0..8 {
+			colors[NamedColor::DimBlack as usize + i] = dim(theme.ansi[i]);
+		}
+		colors[NamedColor::BrightForeground as usize] = theme.foreground;
+		colors[NamedColor::DimForeground as usize] = dim(theme.foreground);
+
+		Self(colors)
+	}
+
+	/// Apply the colors a program changed at runtime (OSC 4/10/11/12)
+	pub fn with_overrides(&self, overrides: &Colors) -> Self {
+		let mut palette = self.clone();
+		for (i, color) in palette.0.iter_mut().enumerate() {
+			if let Some(over) = overrides[i] {
+				*color = over;
+			}
+		}
+		palette
+	}
+
+	pub fn get(&self, named: NamedColor) -> Rgb {
+		self.0[named as usize]
+	}
+
+	pub fn resolve_fg(&self, color: Color, flags: Flags) -> Rgb {
+		let dimmed = flags.contains(Flags::DIM);
+		match color {
+			Color::Named(named) if dimmed => self.get(named.to_dim()),
+			Color::Named(named) => self.get(named),
+			// The 8 normal colors have their own dim variants, bright ones dim to normal
+			Color::Indexed(idx @ 0..8) if dimmed => self.0[NamedColor::DimBlack as usize + idx as usize],
+			Color::Indexed(idx @ 8..16) if dimmed => self.0[idx as usize - 8],
+			Color::Indexed(idx) if dimmed => dim(self.0[idx as usize]),
+			Color::Indexed(idx) => self.0[idx as usize],
+			Color::Spec(rgb) if dimmed => dim(rgb),
+			Color::Spec(rgb) => rgb,
+		}
+	}
+
+	fn resolve_bg(&self, color: Color) -> Rgb {
+		match color {
+			Color::Named(named) => self.get(named),
+			Color::Indexed(idx) => self.0[idx as usize],
+			Color::Spec(rgb) => rgb,
+		}
+	}
+
+	/// The foreground and background a cell is actually drawn with
+	pub fn cell_colors(&self, cell: &Cell) -> (Rgba<u8>, Rgba<u8>) {
+		let mut fg = self.resolve_fg(cell.fg, cell.flags);
+		let mut bg = self.resolve_bg(cell.bg);
+
+		if cell.flags.contains(Flags::INVERSE) {
+			std::mem::swap(&mut fg, &mut bg);
+		}
+		if cell.flags.contains(Flags::HIDDEN) {
+			fg = bg;
+		}
+
+		(to_rgba(fg), to_rgba(bg))
+	}
+}
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result, anyhow};
+use alacritty_terminal::vte::ansi::Rgb;
+use std::path::Path;
+use crate::theme::Theme;
+use crate::themes::{self, parse_hex};
+
+const ANSI_NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// A scheme being read from a file, before we know it has every color
+#[derive(Default)]
+struct PartialTheme {
+	foreground: Option<Rgb>,
+	background: Option<Rgb>,
+	cursor: Option<Rgb>,
+	ansi: [Option<Rgb>; 16],
+}
+
+impl PartialTheme {
+	/// Check every color was found, naming the missing ones if not
+	fn finish(self) -> Result<Theme> {
+		let mut missing = Vec::new();
+		if self.foreground.is_none() {
+			missing.push("foreground".to_string());
+		}
+		if self.background.is_none() {
+			missing.push("background".to_string());
+		}
+		for (i, color) in self.ansi.iter().enumerate() {
+			if color.is_none() {
+				let prefix = if i < 8 { "" } else { "bright " };
+				missing.push(format!("{}{}", prefix, ANSI_NAMES[i % 8]));
+			}
+		}
+
+		if !missing.is_empty() {
+			return Err(anyhow!("Incomplete color scheme, missing {}", missing.join(", ")));
+		}
+
+		let foreground = self.foreground.unwrap();
+		Ok(Theme {
+			foreground,
+			background: self.background.unwrap(),
+			cursor: self.cursor.unwrap_or(foreground),
+			ansi: self.ansi.map(Option::unwrap),
+		})
+	}
+}
+
+/// Whether `Set Theme` was given something that looks like a scheme file
+pub fn is_theme_file(value: &str) -> bool {
+	let path = Path::new(value);
+	path.is_file()
+		|| path.extension().is_some_and(|ext| {
+			matches!(
+				ext.to_string_lossy().to_lowercase().as_str(),
+				"toml" | "itermcolors" | "json" | "yaml" | "yml"
+			)
+		})
+}
+
+/// Load a color scheme written for another terminal, picking the format
+/// from the file's extension
+pub fn load(path: &Path) -> Result<Theme> {
+	let contents = std::fs::read_to_string(path)
+		.with_context(|| format!("Failed to read theme {}", path.display()))?;
+
+	let extension = path
+		.extension()
+		.map(|ext| ext.to_string_lossy().to_lowercase())
+		.unwrap_or_default();
+
+	let theme = match extension.as_str() {
+		"toml" => from_alacritty(&contents),
+		"itermcolors" => from_iterm(&contents),
+		"json" => themes::from_json(&contents),
+		"yaml" | "yml" => from_base16(&contents),
+		_ => Err(anyhow!(
+			"Unknown theme format, expected .toml (alacritty), .itermcolors (iTerm2), \
+			.json (Windows Terminal) or .yaml (base16)"
+		)),
+	};
+
+	theme.with_context(|| format!("Failed to load theme {}", path.display()))
+}
+
+/// An alacritty `[colors]` table
+fn from_alacritty(contents: &str) -> Result<Theme> {
+	let config: toml::Table = contents.parse().context("Invalid TOML")?;
+	let colors = config
+		.get("colors")
+		.and_then(toml::Value::as_table)
+		.ok_or_else(|| anyhow!("No [colors] table"))?;
+
+	let color = |section: &str, key: &str| -> Result<Option<Rgb>> {
+		let value = colors
+			.get(section)
+			.and_then(toml::Value::as_table)
+			.and_then(|table| table.get(key));
+		match value {
+			// Colors that follow whatever cell is under them, which a theme
+			// can't express, so they count as not set
+			Some(toml::Value::String(name)) if name == "CellForeground" || name == "CellBackground" => Ok(None),
+			Some(toml::Value::String(hex)) => Ok(Some(parse_hex(hex)?)),
+			Some(other) => Err(anyhow!("colors.{}.{} should be a color, got {}", section, key, other)),
+			None => Ok(None),
+		}
+	};
+
+	let mut theme = PartialTheme {
+		foreground: color("primary", "foreground")?,
+		background: color("primary", "background")?,
+		cursor: color("cursor", "cursor")?,
+		..Default::default()
+	};
+	for (i, name) in ANSI_NAMES.iter().enumerate() {
+		theme.ansi[i] = color("normal", name)?;
+		theme.ansi[i + 8] = color("bright", name)?;
+	}
+
+	theme.finish()
+}
+
+/// An iTerm2 `.itermcolors` property list
+fn from_iterm(contents: &str) -> Result<Theme> {
+	let plist = plist::Value::from_reader_xml(contents.as_bytes()).context("Invalid property list")?;
+	let dict = plist
+		.as_dictionary()
+		.ok_or_else(|| anyhow!("Expected a dictionary of colors"))?;
+
+	let color = |key: &str| -> Result<Option<Rgb>> {
+		let Some(value) = dict.get(key) else {
+			return Ok(None);
+		};
+		let components = value
+			.as_dictionary()
+			.ok_or_else(|| anyhow!("'{}' should be a color dictionary", key))?;
+		let component = |name: &str| -> Result<u8> {
+			let value = components
+				.get(name)
+				.and_then(|v| v.as_real().or_else(|| v.as_signed_integer().map(|i| i as f64)))
+				.ok_or_else(|| anyhow!("'{}' is missing its {}", key, name))?;
+			Ok((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+		};
+
+		Ok(Some(Rgb {
+			r: component("Red Component")?,
+			g: component("Green Component")?,
+			b: component("Blue Component")?,
+		}))
+	};
+
+	let mut theme = PartialTheme {
+		foreground: color("Foreground Color")?,
+		background: color("Background Color")?,
+		cursor: color("Cursor Color")?,
+		..Default::default()
+	};
+	for (i, slot) in theme.ansi.iter_mut().enumerate() {
+		*slot = color(&format!("Ansi {} Color", i))?;
+	}
+
+	theme.finish()
+}
+
+/// A base16 scheme, either the classic flat `baseXX` keys or the newer
+/// format which nests them under `palette`
+fn from_base16(contents: &str) -> Result<Theme> {
+	let yaml: serde_yaml::Value = serde_yaml::from_str(contents).context("Invalid YAML")?;
+	let scheme = match yaml.get("palette") {
+		Some(palette) => palette,
+		None => &yaml,
+	};
+
+	let base = |n: u8| -> Result<Option<Rgb>> {
+		let key = format!("base{:02X}", n);
+		match scheme.get(&key) {
+			Some(serde_yaml::Value::String(hex)) => Ok(Some(parse_hex(hex)?)),
+			// Unquoted all-digit colors like 282828 come through as numbers
+			Some(serde_yaml::Value::Number(n)) if n.is_u64() => {
+				Ok(Some(parse_hex(&format!("{:06}", n.as_u64().unwrap()))?))
+			}
+			Some(other) => Err(anyhow!("{} should be a color, got {:?}", key, other)),
+			None => Ok(None),
+		}
+	};
+
+	// The standard base16 terminal mapping, as used by base16-shell
+	let ansi = [
+		0x00, 0x08, 0x0B, 0x0A, 0x0D, 0x0E, 0x0C, 0x05,
+		0x03, 0x08, 0x0B, 0x0A, 0x0D, 0x0E, 0x0C, 0x07,
+	];
+
+	let mut theme = PartialTheme {
+		foreground: base(0x05)?,
+		background: base(0x00)?,
+		cursor: base(0x05)?,
+		..Default::default()
+	};
+	for (slot, n) in theme.ansi.iter_mut().zip(ansi) {
+		*slot = base(n)?;
+	}
+
+	theme.finish().context("base16 schemes need base00 through base0F")
+}
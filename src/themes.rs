@@ -0,0 +1,242 @@
+use anyhow::{Context, Result, anyhow};
+use alacritty_terminal::vte::ansi::Rgb;
+use serde::Deserialize;
+use std::path::Path;
+use crate::theme::{Theme, rgb};
+use crate::theme_import;
+
+macro_rules! theme {
+	(
+		bg: $bg:literal, fg: $fg:literal, cursor: $cursor:literal,
+		normal: [$($n:literal),*], bright: [$($b:literal),*] $(,)?
+	) => {
+		Theme {
+			foreground: rgb($fg),
+			background: rgb($bg),
+			cursor: rgb($cursor),
+			ansi: [$(rgb($n)),*, $(rgb($b)),*],
+		}
+	};
+}
+
+/// The theme used when a tape doesn't pick one
+pub const DEFAULT_THEME: Theme = theme! {
+	bg: 0x171717, fg: 0xdddddd, cursor: 0xdddddd,
+	normal: [0x282a2e, 0xd74e6f, 0x31bb71, 0xd3e561, 0x8056ff, 0xed61d7, 0x04d7d7, 0xbfbfbf],
+	bright: [0x4d4d4d, 0xfe5f86, 0x00d787, 0xebff71, 0x9b79ff, 0xff7af2, 0x7af9ff, 0xe6e6e6],
+};
+
+/// Every theme that can be picked by name with `Set Theme`
+pub static THEMES: &[(&str, Theme)] = &[
+	("Catppuccin Frappe", theme! {
+		bg: 0x303446, fg: 0xc6d0f5, cursor: 0xf2d5cf,
+		normal: [0x51576d, 0xe78284, 0xa6d189, 0xe5c890, 0x8caaee, 0xf4b8e4, 0x81c8be, 0xb5bfe2],
+		bright: [0x626880, 0xe78284, 0xa6d189, 0xe5c890, 0x8caaee, 0xf4b8e4, 0x81c8be, 0xa5adce],
+	}),
+	("Catppuccin Latte", theme! {
+		bg: 0xeff1f5, fg: 0x4c4f69, cursor: 0xdc8a78,
+		normal: [0x5c5f77, 0xd20f39, 0x40a02b, 0xdf8e1d, 0x1e66f5, 0xea76cb, 0x179299, 0xacb0be],
+		bright: [0x6c6f85, 0xd20f39, 0x40a02b, 0xdf8e1d, 0x1e66f5, 0xea76cb, 0x179299, 0xbcc0cc],
+	}),
+	("Catppuccin Macchiato", theme! {
+		bg: 0x24273a, fg: 0xcad3f5, cursor: 0xf4dbd6,
+		normal: [0x494d64, 0xed8796, 0xa6da95, 0xeed49f, 0x8aadf4, 0xf5bde6, 0x8bd5ca, 0xb8c0e0],
+		bright: [0x5b6078, 0xed8796, 0xa6da95, 0xeed49f, 0x8aadf4, 0xf5bde6, 0x8bd5ca, 0xa5adcb],
+	}),
+	("Catppuccin Mocha", theme! {
+		bg: 0x1e1e2e, fg: 0xcdd6f4, cursor: 0xf5e0dc,
+		normal: [0x45475a, 0xf38ba8, 0xa6e3a1, 0xf9e2af, 0x89b4fa, 0xf5c2e7, 0x94e2d5, 0xbac2de],
+		bright: [0x585b70, 0xf38ba8, 0xa6e3a1, 0xf9e2af, 0x89b4fa, 0xf5c2e7, 0x94e2d5, 0xa6adc8],
+	}),
+	("Default", DEFAULT_THEME),
+	("Dracula", theme! {
+		bg: 0x282a36, fg: 0xf8f8f2, cursor: 0xf8f8f2,
+		normal: [0x21222c, 0xff5555, 0x50fa7b, 0xf1fa8c, 0xbd93f9, 0xff79c6, 0x8be9fd, 0xf8f8f2],
+		bright: [0x6272a4, 0xff6e6e, 0x69ff94, 0xffffa5, 0xd6acff, 0xff92df, 0xa4ffff, 0xffffff],
+	}),
+	("Gruvbox Dark", theme! {
+		bg: 0x282828, fg: 0xebdbb2, cursor: 0xebdbb2,
+		normal: [0x282828, 0xcc241d, 0x98971a, 0xd79921, 0x458588, 0xb16286, 0x689d6a, 0xa89984],
+		bright: [0x928374, 0xfb4934, 0xb8bb26, 0xfabd2f, 0x83a598, 0xd3869b, 0x8ec07c, 0xebdbb2],
+	}),
+	("Gruvbox Light", theme! {
+		bg: 0xfbf1c7, fg: 0x3c3836, cursor: 0x3c3836,
+		normal: [0xfbf1c7, 0xcc241d, 0x98971a, 0xd79921, 0x458588, 0xb16286, 0x689d6a, 0x7c6f64],
+		bright: [0x928374, 0x9d0006, 0x79740e, 0xb57614, 0x076678, 0x8f3f71, 0x427b58, 0x3c3836],
+	}),
+	("Monokai", theme! {
+		bg: 0x272822, fg: 0xf8f8f2, cursor: 0xf8f8f0,
+		normal: [0x272822, 0xf92672, 0xa6e22e, 0xf4bf75, 0x66d9ef, 0xae81ff, 0xa1efe4, 0xf8f8f2],
+		bright: [0x75715e, 0xf92672, 0xa6e22e, 0xf4bf75, 0x66d9ef, 0xae81ff, 0xa1efe4, 0xf9f8f5],
+	}),
+	("Nord", theme! {
+		bg: 0x2e3440, fg: 0xd8dee9, cursor: 0xd8dee9,
+		normal: [0x3b4252, 0xbf616a, 0xa3be8c, 0xebcb8b, 0x81a1c1, 0xb48ead, 0x88c0d0, 0xe5e9f0],
+		bright: [0x4c566a, 0xbf616a, 0xa3be8c, 0xebcb8b, 0x81a1c1, 0xb48ead, 0x8fbcbb, 0xeceff4],
+	}),
+	("One Dark", theme! {
+		bg: 0x282c34, fg: 0xabb2bf, cursor: 0x528bff,
+		normal: [0x282c34, 0xe06c75, 0x98c379, 0xe5c07b, 0x61afef, 0xc678dd, 0x56b6c2, 0xabb2bf],
+		bright: [0x5c6370, 0xe06c75, 0x98c379, 0xe5c07b, 0x61afef, 0xc678dd, 0x56b6c2, 0xffffff],
+	}),
+	("Solarized Dark", theme! {
+		bg: 0x002b36, fg: 0x839496, cursor: 0x93a1a1,
+		normal: [0x073642, 0xdc322f, 0x859900, 0xb58900, 0x268bd2, 0xd33682, 0x2aa198, 0xeee8d5],
+		bright: [0x002b36, 0xcb4b16, 0x586e75, 0x657b83, 0x839496, 0x6c71c4, 0x93a1a1, 0xfdf6e3],
+	}),
+	("Solarized Light", theme! {
+		bg: 0xfdf6e3, fg: 0x657b83, cursor: 0x586e75,
+		normal: [0x073642, 0xdc322f, 0x859900, 0xb58900, 0x268bd2, 0xd33682, 0x2aa198, 0xeee8d5],
+		bright: [0x002b36, 0xcb4b16, 0x586e75, 0x657b83, 0x839496, 0x6c71c4, 0x93a1a1, 0xfdf6e3],
+	}),
+	("Tokyo Night", theme! {
+		bg: 0x1a1b26, fg: 0xc0caf5, cursor: 0xc0caf5,
+		normal: [0x15161e, 0xf7768e, 0x9ece6a, 0xe0af68, 0x7aa2f7, 0xbb9af7, 0x7dcfff, 0xa9b1d6],
+		bright: [0x414868, 0xf7768e, 0x9ece6a, 0xe0af68, 0x7aa2f7, 0xbb9af7, 0x7dcfff, 0xc0caf5],
+	}),
+];
+
+/// Theme names are matched ignoring case, spaces, dashes and underscores
+fn normalize(name: &str) -> String {
+	name.chars()
+		.filter(|c| c.is_alphanumeric())
+		.flat_map(char::to_lowercase)
+		.collect()
+}
+
+pub fn find(name: &str) -> Option<&'static Theme> {
+	let wanted = normalize(name);
+	THEMES
+		.iter()
+		.find(|(theme_name, _)| normalize(theme_name) == wanted)
+		.map(|(_, theme)| theme)
+}
+
+/// Parse a `#rrggbb`, `0xrrggbb` or bare `rrggbb` hex color
+pub fn parse_hex(hex: &str) -> Result<Rgb> {
+	let trimmed = hex.trim();
+	let digits = trimmed
+		.strip_prefix('#')
+		.or_else(|| trimmed.strip_prefix("0x"))
+		.unwrap_or(trimmed);
+	if digits.len() != 6 {
+		return Err(anyhow!("Invalid color '{}', expected #rrggbb", hex));
+	}
+	let value = u32::from_str_radix(digits, 16)
+		.map_err(|_| anyhow!("Invalid color '{}', expected #rrggbb", hex))?;
+	Ok(rgb(value))
+}
+
+/// A theme written inline as JSON, using the same keys as xterm.js and
+/// Windows Terminal
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonTheme {
+	background: String,
+	foreground: String,
+	cursor: Option<String>,
+	cursor_color: Option<String>,
+	black: String,
+	red: String,
+	green: String,
+	yellow: String,
+	blue: String,
+	#[serde(alias = "purple")]
+	magenta: String,
+	cyan: String,
+	white: String,
+	bright_black: String,
+	bright_red: String,
+	bright_green: String,
+	bright_yellow: String,
+	bright_blue: String,
+	#[serde(alias = "brightPurple")]
+	bright_magenta: String,
+	bright_cyan: String,
+	bright_white: String,
+}
+
+impl JsonTheme {
+	fn into_theme(self) -> Result<Theme> {
+		let foreground = parse_hex(&self.foreground)?;
+		let cursor = match self.cursor.or(self.cursor_color) {
+			Some(cursor) => parse_hex(&cursor)?,
+			None => foreground,
+		};
+
+		let ansi = [
+			&self.black, &self.red, &self.green, &self.yellow,
+			&self.blue, &self.magenta, &self.cyan, &self.white,
+			&self.bright_black, &self.bright_red, &self.bright_green, &self.bright_yellow,
+			&self.bright_blue, &self.bright_magenta, &self.bright_cyan, &self.bright_white,
+		];
+
+		let mut theme = Theme {
+			foreground,
+			background: parse_hex(&self.background)?,
+			cursor,
+			ansi: [Rgb::default(); 16],
+		};
+		for (slot, hex) in theme.ansi.iter_mut().zip(ansi) {
+			*slot = parse_hex(hex)?;
+		}
+
+		Ok(theme)
+	}
+}
+
+pub fn from_json(json: &str) -> Result<Theme> {
+	serde_json::from_str::<JsonTheme>(json)
+		.context("Invalid JSON theme")?
+		.into_theme()
+}
+
+/// Resolve the value of `Set Theme`, either a built in theme's name, an
+/// inline JSON theme or a path to another terminal's color scheme
+pub fn resolve(value: &str) -> Result<Theme> {
+	if value.trim_start().starts_with('{') {
+		return from_json(value);
+	}
+	if theme_import::is_theme_file(value) {
+		return theme_import::load(Path::new(value));
+	}
+
+	find(value).cloned().ok_or_else(|| {
+		anyhow!("Unknown theme '{}', run `dvd themes` to see the available ones", value)
+	})
+}
+
+fn hex(color: Rgb) -> String {
+	format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn swatch(color: Rgb) -> String {
+	format!(r#"<span style="color:{0}" title="{0}">██</span>"#, hex(color))
+}
+
+/// Print every built in theme, one per line or as a markdown preview table
+pub fn list(markdown: bool) {
+	if !markdown {
+		for (name, _) in THEMES {
+			println!("{name}");
+		}
+		return;
+	}
+
+	println!("| Name | Background | Foreground | Colors | Bright colors |");
+	println!("| ---- | ---------- | ---------- | ------ | ------------- |");
+	for (name, theme) in THEMES {
+		let normal: String = theme.ansi[..8].iter().map(|c| swatch(*c)).collect();
+		let bright: String = theme.ansi[8..].iter().map(|c| swatch(*c)).collect();
+		println!(
+			"| {} | {} `{}` | {} `{}` | {} | {} |",
+			name,
+			swatch(theme.background),
+			hex(theme.background),
+			swatch(theme.foreground),
+			hex(theme.foreground),
+			normal,
+			bright,
+		);
+	}
+}
@@ -45,6 +45,7 @@ pub enum TokenType {
     Minutes,
     Px,
     Seconds,
+    WordsPerMinute,
 
     // Special
     Eof,
@@ -94,6 +95,7 @@ pub enum TokenType {
     Paste,
     Shell,
     Env,
+    EnvFile,
 
     // Settings
     FontFamily,
@@ -102,6 +104,8 @@ pub enum TokenType {
     PlaybackSpeed,
     Height,
     Width,
+    Columns,
+    Rows,
     LetterSpacing,
     LineHeight,
     TypingSpeed,
@@ -117,6 +121,15 @@ pub enum TokenType {
     WaitTimeout,
     WaitPattern,
     CursorBlink,
+    InheritEnv,
+    FontLigatures,
+    Tail,
+    TypingJitter,
+    Seed,
+    TypoRate,
+    LoopCount,
+    EmbedFont,
+    CsvColors,
 }
 
 impl fmt::Display for TokenType {
@@ -149,6 +162,7 @@ pub static KEYWORDS: LazyLock<HashMap<Cow<'static, str>, TokenType>> = LazyLock:
     m.insert(Cow::Borrowed("ms"), TokenType::Milliseconds);
     m.insert(Cow::Borrowed("s"), TokenType::Seconds);
     m.insert(Cow::Borrowed("m"), TokenType::Minutes);
+    m.insert(Cow::Borrowed("wpm"), TokenType::WordsPerMinute);
     m.insert(Cow::Borrowed("Set"), TokenType::Set);
     m.insert(Cow::Borrowed("Sleep"), TokenType::Sleep);
     m.insert(Cow::Borrowed("Type"), TokenType::Type);
@@ -190,6 +204,8 @@ pub static KEYWORDS: LazyLock<HashMap<Cow<'static, str>, TokenType>> = LazyLock:
     m.insert(Cow::Borrowed("Padding"), TokenType::Padding);
     m.insert(Cow::Borrowed("Theme"), TokenType::Theme);
     m.insert(Cow::Borrowed("Width"), TokenType::Width);
+    m.insert(Cow::Borrowed("Columns"), TokenType::Columns);
+    m.insert(Cow::Borrowed("Rows"), TokenType::Rows);
     m.insert(Cow::Borrowed("LoopOffset"), TokenType::LoopOffset);
     m.insert(Cow::Borrowed("WaitTimeout"), TokenType::WaitTimeout);
     m.insert(Cow::Borrowed("WaitPattern"), TokenType::WaitPattern);
@@ -201,6 +217,16 @@ pub static KEYWORDS: LazyLock<HashMap<Cow<'static, str>, TokenType>> = LazyLock:
     m.insert(Cow::Borrowed("Copy"), TokenType::Copy);
     m.insert(Cow::Borrowed("Paste"), TokenType::Paste);
     m.insert(Cow::Borrowed("Env"), TokenType::Env);
+    m.insert(Cow::Borrowed("EnvFile"), TokenType::EnvFile);
+    m.insert(Cow::Borrowed("InheritEnv"), TokenType::InheritEnv);
+    m.insert(Cow::Borrowed("FontLigatures"), TokenType::FontLigatures);
+    m.insert(Cow::Borrowed("Tail"), TokenType::Tail);
+    m.insert(Cow::Borrowed("TypingJitter"), TokenType::TypingJitter);
+    m.insert(Cow::Borrowed("Seed"), TokenType::Seed);
+    m.insert(Cow::Borrowed("TypoRate"), TokenType::TypoRate);
+    m.insert(Cow::Borrowed("LoopCount"), TokenType::LoopCount);
+    m.insert(Cow::Borrowed("EmbedFont"), TokenType::EmbedFont);
+    m.insert(Cow::Borrowed("CsvColors"), TokenType::CsvColors);
     m
 });
 
@@ -218,6 +244,8 @@ pub fn is_setting(token_type: &TokenType) -> bool {
             | TokenType::PlaybackSpeed
             | TokenType::Height
             | TokenType::Width
+            | TokenType::Columns
+            | TokenType::Rows
             | TokenType::Padding
             | TokenType::LoopOffset
             | TokenType::MarginFill
@@ -228,6 +256,15 @@ pub fn is_setting(token_type: &TokenType) -> bool {
             | TokenType::CursorBlink
             | TokenType::WaitTimeout
             | TokenType::WaitPattern
+            | TokenType::InheritEnv
+            | TokenType::FontLigatures
+            | TokenType::Tail
+            | TokenType::TypingJitter
+            | TokenType::Seed
+            | TokenType::TypoRate
+            | TokenType::LoopCount
+            | TokenType::EmbedFont
+            | TokenType::CsvColors
     )
 }
 
@@ -255,6 +292,7 @@ pub fn is_command(token_type: &TokenType) -> bool {
             | TokenType::Copy
             | TokenType::Paste
             | TokenType::Wait
+            | TokenType::EnvFile
     )
 }
 
@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+/// A small, fast generator whose output only depends on the seed, so a tape
+/// types the same way on every machine and every build
+#[derive(Debug, Clone)]
+pub struct Rng {
+	state: u64,
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Self { state: seed }
+	}
+
+	/// SplitMix64
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+
+	/// Uniform in `0..n`
+	pub fn below(&mut self, n: usize) -> usize {
+		(self.next_u64() % n as u64) as usize
+	}
+
+	/// Uniform in `[0, 1)`
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
+
+/// What the backspace key sends
+const BACKSPACE: char = '\x7f';
+
+/// The keyboard, row by row, each row shifted a little right of the one above
+const QWERTY: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+
+/// Keys a finger aiming for `c` could land on instead
+fn neighbours(c: char) -> Vec<char> {
+	let lower = c.to_ascii_lowercase();
+	let Some((row, column)) = QWERTY
+		.iter()
+		.enumerate()
+		.find_map(|(row, keys)| keys.find(lower).map(|column| (row, column)))
+	else {
+		return Vec::new();
+	};
+
+	let mut keys = Vec::new();
+	let mut add = |row: usize, column: Option<usize>| {
+		if let Some(key) = QWERTY.get(row).zip(column).and_then(|(keys, column)| keys.chars().nth(column)) {
+			keys.push(if c.is_ascii_uppercase() { key.to_ascii_uppercase() } else { key });
+		}
+	};
+	add(row, column.checked_sub(1));
+	add(row, Some(column + 1));
+	if row > 0 {
+		add(row - 1, Some(column));
+		add(row - 1, Some(column + 1));
+	}
+	add(row + 1, column.checked_sub(1));
+	add(row + 1, Some(column));
+	keys
+}
+
+/// Turns the text of a `Type` into keystrokes, and decides how long each
+/// one takes.
+///
+/// Without jitter every key takes exactly the typing speed. With it, each
+/// key varies around that speed, and the typist stops a little after words
+/// and longer after sentences, like a person would. With typos, it now and
+/// then hits a neighbouring key, notices, and backspaces to fix it.
+#[derive(Debug, Clone)]
+pub struct Typist {
+	rng: Rng,
+	/// How far a keystroke may stray from the typing speed, as a fraction of it
+	jitter: f64,
+	/// Chance of any letter or digit being mistyped
+	typo_rate: f64,
+}
+
+impl Typist {
+	pub fn new(seed: u64, jitter: f32, typo_rate: f32) -> Self {
+		Self {
+			rng: Rng::new(seed),
+			jitter: jitter.max(0.0) as f64,
+			typo_rate: typo_rate.clamp(0.0, 1.0) as f64,
+		}
+	}
+
+	/// The keys to press to type `text` at `rate`, each with how long to wait
+	/// after it. Whatever mistakes are made get corrected, so the result is
+	/// always `text`.
+	pub fn keystrokes(&mut self, text: &str, rate: Duration) -> Vec<(char, Duration)> {
+		let chars: Vec<char> = text.chars().collect();
+		let mut keys = Vec::with_capacity(chars.len());
+
+		for (i, &c) in chars.iter().enumerate() {
+			if let Some(typo) = self.typo(c) {
+				keys.push((typo, self.delay(typo, rate)));
+
+				// It can take a key or two of the same word to notice
+				let noticed = self.rng.below(3);
+				let carried_on = chars[i + 1..]
+					.iter()
+					.take(noticed)
+					.take_while(|c| c.is_ascii_alphanumeric())
+					.count();
+				for &c in &chars[i + 1..i + 1 + carried_on] {
+					keys.push((c, self.delay(c, rate)));
+				}
+
+				if let Some((_, delay)) = keys.last_mut() {
+					*delay += rate.mul_f64(3.0 + 3.0 * self.rng.next_f64());
+				}
+				for _ in 0..=carried_on {
+					keys.push((BACKSPACE, self.delay(BACKSPACE, rate)));
+				}
+			}
+
+			keys.push((c, self.delay(c, rate)));
+		}
+
+		keys
+	}
+
+	/// The key hit instead of `c`, if this one is a miss
+	fn typo(&mut self, c: char) -> Option<char> {
+		if self.typo_rate == 0.0 || !c.is_ascii_alphanumeric() || self.rng.next_f64() >= self.typo_rate {
+			return None;
+		}
+		let neighbours = neighbours(c);
+		(!neighbours.is_empty()).then(|| neighbours[self.rng.below(neighbours.len())])
+	}
+
+	/// How long to wait after typing `c` at `rate`
+	fn delay(&mut self, c: char, rate: Duration) -> Duration {
+		if self.jitter == 0.0 {
+			return rate;
+		}
+
+		// Most keystrokes land near the typing speed, few far from it
+		let spread = self.rng.next_f64() + self.rng.next_f64() - 1.0;
+		let mut factor = (1.0 + self.jitter * spread).max(0.2);
+
+		let pause = match c {
+			'.' | '!' | '?' => 6.0,
+			',' | ';' | ':' => 3.0,
+			' ' => 1.0,
+			_ => 0.0,
+		};
+		factor += pause * self.jitter * (0.5 + self.rng.next_f64());
+
+		rate.mul_f64(factor)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// What ends up on the line after pressing `keys`
+	fn replay(keys: &[(char, Duration)]) -> String {
+		let mut line = String::new();
+		for &(c, _) in keys {
+			if c == BACKSPACE {
+				line.pop();
+			} else {
+				line.push(c);
+			}
+		}
+		line
+	}
+
+	#[test]
+	fn typos_are_always_corrected() {
+		let texts = [
+			"",
+			"a",
+			"echo 'Hello, World!'",
+			"The QUICK brown fox jumps over 13 lazy dogs.",
+			"git commit -m \"fix: tabs\tand newlines\n\"",
+			"zzzz qqqq 1111 ==== ////",
+			"naïve café → 中文 ok",
+		];
+		let rate = Duration::from_millis(50);
+
+		for seed in 0..500 {
+			for typo_rate in [0.0, 0.05, 0.3, 0.9, 1.0] {
+				let mut typist = Typist::new(seed, 0.5, typo_rate);
+				for text in texts {
+					let keys = typist.keystrokes(text, rate);
+					assert_eq!(
+						replay(&keys),
+						text,
+						"seed {} with typo rate {} mistyped {:?}",
+						seed,
+						typo_rate,
+						text
+					);
+				}
+			}
+		}
+	}
+}